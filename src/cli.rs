@@ -0,0 +1,253 @@
+use clap::{Parser, ValueEnum};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Extra arguments read from the environment, split on whitespace.
+const OPTS_ENV: &str = "GIT_CHECKOUTUI_OPTS";
+
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    args_override_self = true,
+    after_help = "Default options can be set in GIT_CHECKOUTUI_OPTS (e.g. \"--sort=name --no-pr\"). \
+                  They are read before the command line, so an option given on the command line \
+                  wins over the same option from the environment."
+)]
+pub struct Args {
+    /// Run as if started in PATH instead of the current directory, like
+    /// `git -C`
+    #[arg(short = 'C', long = "cwd", value_name = "PATH", value_parser = parse_dir)]
+    pub cwd: Option<PathBuf>,
+
+    /// Order in which branches are listed
+    #[arg(short, long, value_enum, default_value_t = SortMode::Date)]
+    pub sort: SortMode,
+
+    /// Also list tags (checked out as a detached HEAD)
+    #[arg(long)]
+    pub tags: bool,
+
+    /// Leave the checked-out branch out of the list
+    #[arg(long)]
+    pub hide_current: bool,
+
+    /// Group branches under a header per `prefix/`
+    #[arg(long)]
+    pub group: bool,
+
+    /// Show which upstream (e.g. `origin/feature`) each branch tracks
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Show only each branch's name and whether it's ahead of or behind
+    /// its upstream (automatic in terminals under 50 columns)
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Mark branches, PRs and tracking info with Nerd Font icons instead of
+    /// `*`, `#`, "ahead 2" and so on (or `icons = true` in config.toml)
+    #[arg(long)]
+    pub icons: bool,
+
+    /// Check every branch's tip for a GPG signature up front (otherwise only
+    /// as each branch gets selected, since it runs gpg per branch)
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Number the rows; type a number and Enter to jump to that branch
+    /// (not with --type-to-filter, where numbers go into the search)
+    #[arg(long, conflicts_with = "type_to_filter")]
+    pub line_numbers: bool,
+
+    /// Branch to measure "merged" and commits ahead against, e.g. `develop`
+    /// or `upstream/main` (default: what origin/HEAD points at, else
+    /// main/master)
+    #[arg(long, value_name = "NAME")]
+    pub default_branch: Option<String>,
+
+    /// Count every branch's commits ahead of the default branch up front
+    /// (otherwise only as each branch gets selected)
+    #[arg(long)]
+    pub with_stats: bool,
+
+    /// Search by case-insensitive regular expression instead of fuzzy
+    /// matching (toggle with Ctrl-t while searching)
+    #[arg(long)]
+    pub regex: bool,
+
+    /// Filter as you type, like fzf: every printable key goes into the
+    /// search and only the arrow keys move
+    #[arg(long)]
+    pub type_to_filter: bool,
+
+    /// How commit dates are shown
+    #[arg(long, value_enum, default_value_t = DateFormat::Relative)]
+    pub date: DateFormat,
+
+    /// Only list the N most recently committed branches
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub limit: Option<usize>,
+
+    /// Only list branches committed to within this long, e.g. `14d`, `2w`
+    /// or `3m` (days, weeks, months)
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    pub since: Option<Age>,
+
+    /// Number of rows PageUp/PageDown move by (default: the list height)
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub page_size: Option<usize>,
+
+    /// Tallest the list may grow, in rows including its borders and the
+    /// footer (default: 20, or `max-height` in config.toml)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(MIN_HEIGHT as i64..))]
+    pub max_height: Option<u16>,
+
+    /// Take over the whole terminal (alternate screen) instead of drawing
+    /// the list below the prompt
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Give up on fetching PRs with `gh` (or `glab`) after this many seconds
+    #[arg(long, value_name = "SECONDS", default_value = "2", value_parser = parse_seconds)]
+    pub gh_timeout: Duration,
+
+    /// Run `git fetch --all --prune` before listing branches
+    #[arg(long)]
+    pub fetch: bool,
+
+    /// Don't run `gh` or `glab` at all, so no PR info is shown
+    #[arg(long)]
+    pub no_pr: bool,
+
+    /// Ask for PRs even if a run in the last minute saved them
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Print the git commands that would run (checkout, branch creation,
+    /// deletions) instead of running them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the chosen branch name to stdout instead of checking it out
+    /// (the UI is drawn on stderr)
+    #[arg(long, conflicts_with = "dry_run")]
+    pub print: bool,
+
+    /// Print the branch list as JSON instead of opening it
+    #[arg(long, conflicts_with_all = ["print", "back"])]
+    pub json: bool,
+
+    /// Check out the previously checked-out branch (`git checkout -`)
+    /// without opening the list
+    #[arg(long, conflicts_with = "print")]
+    pub back: bool,
+
+    /// After a successful checkout, run this shell command in the repository
+    /// (e.g. "cargo build")
+    #[arg(long, value_name = "CMD", conflicts_with = "print")]
+    pub exec: Option<String>,
+
+    /// Stash uncommitted changes before checking out and pop them after
+    #[arg(long)]
+    pub autostash: bool,
+
+    /// Check out without asking about uncommitted changes, passing `-f` to
+    /// git. This DISCARDS local changes that conflict with the branch.
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Check out branches by running this instead of `git checkout`, e.g.
+    /// "my-wrapper switch {branch}" (the branch is appended if there's no
+    /// `{branch}`); not run through a shell
+    #[arg(long, value_name = "TEMPLATE", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    pub checkout_command: Option<String>,
+
+    /// Use `git switch` instead of `git checkout` (requires git 2.23+)
+    #[arg(long)]
+    pub use_switch: bool,
+}
+
+/// Two borders, one branch row and the footer.
+pub const MIN_HEIGHT: u16 = 4;
+
+impl Args {
+    /// Parses `GIT_CHECKOUTUI_OPTS` followed by the command line, so
+    /// explicit flags override the environment's.
+    pub fn parse_with_env() -> Self {
+        let mut args = std::env::args_os();
+        let program = args.next().unwrap_or_else(|| "git-checkoutui".into());
+        let env_opts = std::env::var(OPTS_ENV).unwrap_or_default();
+        let env_args = env_opts.split_whitespace().map(OsString::from);
+        Self::parse_from(std::iter::once(program).chain(env_args).chain(args))
+    }
+}
+
+fn parse_dir(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err("not a directory".to_string())
+    }
+}
+
+/// How far back `--since` looks, keeping the text it was given for the title.
+#[derive(Clone)]
+pub struct Age {
+    pub seconds: i64,
+    label: String,
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+fn parse_age(s: &str) -> Result<Age, String> {
+    const DAY: i64 = 24 * 60 * 60;
+    let split = s.len() - s.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = s.split_at(split);
+    let unit_seconds = match unit {
+        "d" => DAY,
+        "w" => 7 * DAY,
+        "m" => 30 * DAY,
+        _ => return Err("expected a number of days, weeks or months, e.g. 14d, 2w or 3m".into()),
+    };
+    let count: u32 = count.parse().map_err(|e| format!("{}", e))?;
+    Ok(Age {
+        seconds: i64::from(count) * unit_seconds,
+        label: s.to_string(),
+    })
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{}", e))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortMode {
+    /// Most recently committed first
+    Date,
+    /// Least recently committed first
+    #[value(name = "committerdate-asc")]
+    DateAsc,
+    /// Alphabetical, case-insensitive
+    Name,
+    /// Most recently checked out first, going by HEAD's reflog
+    Recent,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DateFormat {
+    /// e.g. "3 days ago"
+    Relative,
+    /// e.g. "2024-05-01 13:45:00 +0900"
+    Iso,
+    /// e.g. "2024-05-01"
+    Short,
+}
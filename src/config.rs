@@ -0,0 +1,550 @@
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, eyre},
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Returns `$XDG_CONFIG_HOME/git-checkoutui`, or `~/.config/git-checkoutui`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("git-checkoutui"))
+}
+
+/// Reads and parses `name` from the config directory, or returns `None` if
+/// it doesn't exist.
+fn load_file<T: serde::de::DeserializeOwned>(name: &str) -> Result<Option<(PathBuf, T)>> {
+    let Some(path) = config_dir().map(|dir| dir.join(name)) else {
+        return Ok(None);
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", path.display())),
+    };
+    let file =
+        toml::from_str(&text).wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+    Ok(Some((path, file)))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Next,
+    Previous,
+    Quit,
+    Checkout,
+    Delete,
+    Refresh,
+}
+
+/// A key plus the modifiers that must be held with it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    const fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    const fn ctrl(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::CONTROL,
+        }
+    }
+
+    /// Parses names like `j`, `Enter`, `PageDown`, `F5` or `Ctrl-n`.
+    fn parse(name: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = name;
+        loop {
+            if let Some(stripped) = strip_prefix_ignore_case(rest, "ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = stripped;
+            } else if let Some(stripped) = strip_prefix_ignore_case(rest, "alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = stripped;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "delete" | "del" => KeyCode::Delete,
+                f => KeyCode::F(f.strip_prefix('f')?.parse().ok()?),
+            },
+        };
+        Some(Self { code, modifiers })
+    }
+
+    fn name(&self) -> String {
+        let code = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::PageUp => "PageUp".to_string(),
+            KeyCode::PageDown => "PageDown".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::Delete => "Delete".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            other => format!("{:?}", other),
+        };
+        let mut name = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            name.push_str("Ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            name.push_str("Alt-");
+        }
+        name + &code
+    }
+
+    fn matches(&self, key: &KeyEvent) -> bool {
+        // Shift is already reflected in the character itself (e.g. `D`).
+        let modifiers = key.modifiers - KeyModifiers::SHIFT;
+        self.code == key.code && self.modifiers == modifiers
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+        .filter(|rest| !rest.is_empty())
+}
+
+pub struct KeyConfig {
+    bindings: Vec<(Action, Vec<KeyBinding>)>,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        use KeyCode::*;
+        Self {
+            bindings: vec![
+                (
+                    Action::Next,
+                    vec![KeyBinding::new(Down), KeyBinding::new(Char('j'))],
+                ),
+                (
+                    Action::Previous,
+                    vec![KeyBinding::new(Up), KeyBinding::new(Char('k'))],
+                ),
+                (Action::Quit, vec![KeyBinding::new(Char('q'))]),
+                (Action::Checkout, vec![KeyBinding::new(Enter)]),
+                (Action::Delete, vec![KeyBinding::new(Char('d'))]),
+                (
+                    Action::Refresh,
+                    vec![KeyBinding::new(F(5)), KeyBinding::ctrl('r')],
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyNames {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    next: Option<KeyNames>,
+    previous: Option<KeyNames>,
+    quit: Option<KeyNames>,
+    checkout: Option<KeyNames>,
+    delete: Option<KeyNames>,
+    refresh: Option<KeyNames>,
+}
+
+impl KeyConfig {
+    /// Loads `keys.toml` from the config directory, using the defaults for
+    /// any action that isn't mapped there.
+    pub fn load() -> Result<Self> {
+        let mut config = Self::default();
+        let Some((path, file)) = load_file::<KeyFile>("keys.toml")? else {
+            return Ok(config);
+        };
+
+        let entries = [
+            (Action::Next, "next", file.next),
+            (Action::Previous, "previous", file.previous),
+            (Action::Quit, "quit", file.quit),
+            (Action::Checkout, "checkout", file.checkout),
+            (Action::Delete, "delete", file.delete),
+            (Action::Refresh, "refresh", file.refresh),
+        ];
+        for (action, field, names) in entries {
+            let Some(names) = names else {
+                continue;
+            };
+            let names = match names {
+                KeyNames::One(name) => vec![name],
+                KeyNames::Many(names) => names,
+            };
+            let keys = names
+                .iter()
+                .map(|name| {
+                    KeyBinding::parse(name).ok_or_else(|| {
+                        eyre!(
+                            "invalid key name '{}' for '{}' in {}",
+                            name,
+                            field,
+                            path.display()
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if let Some((_, bindings)) = config.bindings.iter_mut().find(|(a, _)| *a == action) {
+                *bindings = keys;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Lists the keys bound to `action`, for the help overlay.
+    pub fn describe(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, bindings)| {
+                bindings
+                    .iter()
+                    .map(KeyBinding::name)
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the action bound to `key`, if any.
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, bindings)| bindings.iter().any(|b| b.matches(key)))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// Colors for each role in the UI.
+pub struct Theme {
+    pub current: Color,
+    pub current_row: Color,
+    pub date: Color,
+    /// Dates of commits newer than `recent_days`.
+    pub date_recent: Color,
+    /// Dates of commits older than `stale_days`, also dimmed.
+    pub date_stale: Color,
+    pub recent_days: u32,
+    pub stale_days: u32,
+    pub tracking: Color,
+    pub author: Color,
+    pub remote: Color,
+    /// Names of branches whose upstream was deleted.
+    pub gone: Color,
+    /// Names of branches that were never pushed.
+    pub local: Color,
+    pub tag: Color,
+    pub pr: Color,
+    pub pr_draft: Color,
+    pub pr_merged: Color,
+    pub pr_closed: Color,
+    pub highlight: Color,
+    pub search_match: Color,
+    pub prompt: Color,
+    pub danger: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            current: Color::Green,
+            current_row: Color::Indexed(236),
+            date: Color::Yellow,
+            date_recent: Color::Green,
+            date_stale: Color::Red,
+            recent_days: 1,
+            stale_days: 60,
+            tracking: Color::Cyan,
+            author: Color::DarkGray,
+            remote: Color::Cyan,
+            gone: Color::DarkGray,
+            local: Color::LightMagenta,
+            tag: Color::Blue,
+            pr: Color::Magenta,
+            pr_draft: Color::Gray,
+            pr_merged: Color::Green,
+            pr_closed: Color::DarkGray,
+            highlight: Color::Green,
+            search_match: Color::LightRed,
+            prompt: Color::Cyan,
+            danger: Color::Red,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ThemeFile {
+    current: Option<String>,
+    current_row: Option<String>,
+    date: Option<String>,
+    date_recent: Option<String>,
+    date_stale: Option<String>,
+    recent_days: Option<u32>,
+    stale_days: Option<u32>,
+    tracking: Option<String>,
+    author: Option<String>,
+    remote: Option<String>,
+    gone: Option<String>,
+    local: Option<String>,
+    tag: Option<String>,
+    pr: Option<String>,
+    pr_draft: Option<String>,
+    pr_merged: Option<String>,
+    pr_closed: Option<String>,
+    highlight: Option<String>,
+    search_match: Option<String>,
+    prompt: Option<String>,
+    danger: Option<String>,
+}
+
+impl Theme {
+    /// This theme with every color left to the terminal, for `NO_COLOR`.
+    /// Bold, dim, reversed and so on still apply.
+    pub fn without_colors(self) -> Self {
+        Self {
+            recent_days: self.recent_days,
+            stale_days: self.stale_days,
+            current: Color::Reset,
+            current_row: Color::Reset,
+            date: Color::Reset,
+            date_recent: Color::Reset,
+            date_stale: Color::Reset,
+            tracking: Color::Reset,
+            author: Color::Reset,
+            remote: Color::Reset,
+            gone: Color::Reset,
+            local: Color::Reset,
+            tag: Color::Reset,
+            pr: Color::Reset,
+            pr_draft: Color::Reset,
+            pr_merged: Color::Reset,
+            pr_closed: Color::Reset,
+            highlight: Color::Reset,
+            search_match: Color::Reset,
+            prompt: Color::Reset,
+            danger: Color::Reset,
+        }
+    }
+
+    /// Loads `theme.toml` from the config directory. Colors are names like
+    /// `yellow` or `light-blue`, hex like `#ffaa00`, or 256-color indices.
+    pub fn load() -> Result<Self> {
+        let mut theme = Self::default();
+        let Some((path, file)) = load_file::<ThemeFile>("theme.toml")? else {
+            return Ok(theme);
+        };
+
+        let entries = [
+            (&mut theme.current, "current", file.current),
+            (&mut theme.current_row, "current-row", file.current_row),
+            (&mut theme.date, "date", file.date),
+            (&mut theme.date_recent, "date-recent", file.date_recent),
+            (&mut theme.date_stale, "date-stale", file.date_stale),
+            (&mut theme.tracking, "tracking", file.tracking),
+            (&mut theme.author, "author", file.author),
+            (&mut theme.remote, "remote", file.remote),
+            (&mut theme.gone, "gone", file.gone),
+            (&mut theme.local, "local", file.local),
+            (&mut theme.tag, "tag", file.tag),
+            (&mut theme.pr, "pr", file.pr),
+            (&mut theme.pr_draft, "pr-draft", file.pr_draft),
+            (&mut theme.pr_merged, "pr-merged", file.pr_merged),
+            (&mut theme.pr_closed, "pr-closed", file.pr_closed),
+            (&mut theme.highlight, "highlight", file.highlight),
+            (&mut theme.search_match, "search-match", file.search_match),
+            (&mut theme.prompt, "prompt", file.prompt),
+            (&mut theme.danger, "danger", file.danger),
+        ];
+        for (color, field, name) in entries {
+            if let Some(name) = name {
+                *color = Color::from_str(&name).map_err(|_| {
+                    eyre!(
+                        "invalid color '{}' for '{}' in {}",
+                        name,
+                        field,
+                        path.display()
+                    )
+                })?;
+            }
+        }
+        if let Some(days) = file.recent_days {
+            theme.recent_days = days;
+        }
+        if let Some(days) = file.stale_days {
+            theme.stale_days = days;
+        }
+        Ok(theme)
+    }
+
+    /// How to draw a commit date `age` seconds old: `date-recent` within
+    /// `recent-days`, dimmed `date-stale` past `stale-days`, `date` between.
+    pub fn date_style(&self, age: i64) -> Style {
+        const DAY: i64 = 24 * 60 * 60;
+        if age < i64::from(self.recent_days) * DAY {
+            Style::default().fg(self.date_recent)
+        } else if age >= i64::from(self.stale_days) * DAY {
+            Style::default()
+                .fg(self.date_stale)
+                .add_modifier(Modifier::DIM)
+        } else {
+            Style::default().fg(self.date)
+        }
+    }
+}
+
+/// What typing after `/` does.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Hide branches that don't match.
+    Filter,
+    /// Keep every branch and jump between matches with `n`/`N`.
+    Jump,
+}
+
+/// General options from `config.toml`.
+pub struct Settings {
+    /// Moving past the last row goes back to the first, and vice versa.
+    pub wrap_navigation: bool,
+    /// Tallest the inline list may grow, borders and footer included.
+    pub max_height: u16,
+    pub search: SearchMode,
+    /// Branches listed first, in this order, whatever the sort.
+    pub pinned: Vec<String>,
+    /// Where new worktrees go, relative to the current one; `{branch}` and
+    /// `{repo}` are replaced with the branch and repository directory names.
+    pub worktree_path: String,
+    /// Run instead of `git checkout <branch>`, split on whitespace; `{branch}`
+    /// is replaced with the branch name.
+    pub checkout_command: Option<String>,
+    /// Branch that "merged" and commits ahead are measured against, instead
+    /// of the detected default branch.
+    pub default_branch: Option<String>,
+    /// Mark rows with Nerd Font icons rather than plain text.
+    pub icons: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            wrap_navigation: true,
+            max_height: 20,
+            search: SearchMode::Filter,
+            pinned: Vec::new(),
+            worktree_path: "../{branch}".to_string(),
+            checkout_command: None,
+            default_branch: None,
+            icons: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct SettingsFile {
+    wrap_navigation: Option<bool>,
+    max_height: Option<u16>,
+    search: Option<SearchMode>,
+    pinned: Option<Vec<String>>,
+    worktree_path: Option<String>,
+    checkout_command: Option<String>,
+    default_branch: Option<String>,
+    icons: Option<bool>,
+}
+
+impl Settings {
+    /// Loads `config.toml` from the config directory, using the defaults for
+    /// anything it doesn't set.
+    pub fn load() -> Result<Self> {
+        let mut settings = Self::default();
+        let Some((path, file)) = load_file::<SettingsFile>("config.toml")? else {
+            return Ok(settings);
+        };
+        if let Some(wrap_navigation) = file.wrap_navigation {
+            settings.wrap_navigation = wrap_navigation;
+        }
+        if let Some(max_height) = file.max_height {
+            if max_height < crate::cli::MIN_HEIGHT {
+                return Err(eyre!(
+                    "'max-height' must be at least {} in {}",
+                    crate::cli::MIN_HEIGHT,
+                    path.display()
+                ));
+            }
+            settings.max_height = max_height;
+        }
+        if let Some(search) = file.search {
+            settings.search = search;
+        }
+        if let Some(pinned) = file.pinned {
+            settings.pinned = pinned;
+        }
+        if let Some(worktree_path) = file.worktree_path {
+            settings.worktree_path = worktree_path;
+        }
+        if let Some(command) = file.checkout_command {
+            if command.trim().is_empty() {
+                return Err(eyre!("'checkout-command' is empty in {}", path.display()));
+            }
+            settings.checkout_command = Some(command);
+        }
+        if let Some(name) = file.default_branch {
+            settings.default_branch = Some(name);
+        }
+        if let Some(icons) = file.icons {
+            settings.icons = icons;
+        }
+        Ok(settings)
+    }
+}
@@ -1,62 +1,689 @@
+mod cli;
+mod config;
+mod state;
+
+use cli::{Age, Args, DateFormat, SortMode};
 use color_eyre::Result;
+use config::{Action, KeyConfig, SearchMode, Settings, Theme};
 use crossterm::{
     ExecutableCommand, cursor,
-    event::{self, Event, KeyCode, KeyModifiers},
-    terminal::{disable_raw_mode, enable_raw_mode},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    terminal::{self, disable_raw_mode, enable_raw_mode},
 };
+use git2::{BranchType, Repository};
 use ratatui::{
     Terminal, TerminalOptions, Viewport,
     prelude::*,
     style::Styled,
-    widgets::{Block, Borders, List, ListItem, ListState},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Wrap,
+    },
 };
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::io::{self, stdout};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, IsTerminal, Write, stdout};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 #[derive(Deserialize)]
 #[allow(non_snake_case)]
 struct PullRequest {
     headRefName: String,
     number: u32,
+    state: PrState,
+    isDraft: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum PrState {
+    Open,
+    Merged,
+    Closed,
+}
+
+/// A GitLab merge request from `glab mr list --output json`.
+#[derive(Deserialize)]
+struct MergeRequest {
+    source_branch: String,
+    iid: u32,
+    /// `opened`, `merged`, `closed` or `locked`.
+    state: String,
+    #[serde(default)]
+    draft: bool,
+}
+
+/// The CLI that PRs are read from and opened with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PrProvider {
+    /// `gh`, for GitHub pull requests.
+    GitHub,
+    /// `glab`, for GitLab merge requests.
+    GitLab,
+}
+
+impl PrProvider {
+    /// `glab` if origin is on GitLab or `gh` isn't installed, otherwise `gh`.
+    /// `None` if neither is installed.
+    fn detect() -> Option<Self> {
+        let installed = |cli: &str| {
+            std::process::Command::new(cli)
+                .arg("--version")
+                .output()
+                .is_ok()
+        };
+        let on_gitlab = git_config("remote.origin.url").is_some_and(|url| url.contains("gitlab"));
+        if on_gitlab && installed("glab") {
+            Some(PrProvider::GitLab)
+        } else if installed("gh") {
+            Some(PrProvider::GitHub)
+        } else if installed("glab") {
+            Some(PrProvider::GitLab)
+        } else {
+            None
+        }
+    }
+
+    fn cli(self) -> &'static str {
+        match self {
+            PrProvider::GitHub => "gh",
+            PrProvider::GitLab => "glab",
+        }
+    }
+
+    /// What PR numbers are written after: `#12` on GitHub, `!12` on GitLab.
+    fn sigil(self) -> char {
+        match self {
+            PrProvider::GitHub => '#',
+            PrProvider::GitLab => '!',
+        }
+    }
+
+    /// Arguments that list every PR, open or not, as JSON.
+    fn list_args(self) -> &'static [&'static str] {
+        match self {
+            PrProvider::GitHub => &[
+                "pr",
+                "list",
+                "--json",
+                "headRefName,number,state,isDraft",
+                "--state",
+                "all",
+                "--limit",
+                "1000",
+            ],
+            PrProvider::GitLab => &[
+                "mr",
+                "list",
+                "--all",
+                "--output",
+                "json",
+                "--per-page",
+                "100",
+            ],
+        }
+    }
+
+    fn parse(self, json: &[u8]) -> HashMap<String, PrInfo> {
+        match self {
+            PrProvider::GitHub => parse_pr_list(json),
+            PrProvider::GitLab => parse_mr_list(json),
+        }
+    }
+
+    /// Arguments that open PR `number` in a browser.
+    fn view_args(self, number: u32) -> [String; 4] {
+        let kind = match self {
+            PrProvider::GitHub => "pr",
+            PrProvider::GitLab => "mr",
+        };
+        [kind, "view", &number.to_string(), "--web"].map(String::from)
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+struct PrInfo {
+    provider: PrProvider,
+    number: u32,
+    state: PrState,
+    is_draft: bool,
+}
+
+impl PrInfo {
+    /// Which PR to show when a branch has several: open ones first, then
+    /// merged, then closed, newest first within each.
+    fn priority(&self) -> (u8, u32) {
+        let state = match self.state {
+            PrState::Open => 2,
+            PrState::Merged => 1,
+            PrState::Closed => 0,
+        };
+        (state, self.number)
+    }
+}
+
+/// Whether a branch's tip commit is signed, as `git log --format=%G?`
+/// reports it.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SignatureStatus {
+    /// A good signature (`G`), or good from a key of unknown validity (`U`).
+    Good,
+    /// A bad signature (`B`).
+    Bad,
+    /// Signed, but the signature can't be checked or its key has expired
+    /// or been revoked (`X`, `Y`, `R`, `E`).
+    Unverified,
+    Unsigned,
+}
+
+impl SignatureStatus {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "G" | "U" => SignatureStatus::Good,
+            "B" => SignatureStatus::Bad,
+            "X" | "Y" | "R" | "E" => SignatureStatus::Unverified,
+            _ => SignatureStatus::Unsigned,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RefType {
+    Branch,
+    /// Listed with `--tags`; checked out as a detached HEAD.
+    Tag,
 }
 
+/// A listed ref; also what `--json` prints.
+#[derive(Serialize)]
 struct BranchInfo {
     name: String,
+    ref_type: RefType,
     tracking_info: String,
     last_commit_date: String,
     last_commit_timestamp: i64,
+    /// The committer's UTC offset in minutes, for absolute dates.
+    last_commit_offset: i32,
+    last_author: String,
+    /// `name` exactly as git has it. `name` replaces bytes that aren't
+    /// UTF-8, so it's only for showing and matching; checkouts use this.
+    #[serde(skip)]
+    raw_name: OsString,
+    /// The last commit's subject, with runs of whitespace collapsed.
+    last_subject: String,
+    /// The tip is reachable from the default branch, so deleting the
+    /// branch loses nothing.
+    is_merged: bool,
+    /// Commits on the branch that the default branch doesn't have; only
+    /// counted with `--with-stats` or once the branch has been selected.
+    commits_ahead_of_main: Option<u32>,
     has_upstream: bool,
-    pr_number: Option<u32>,
+    /// The configured upstream, shortened (e.g. `origin/feature`), even if
+    /// it's gone.
+    upstream: Option<String>,
+    pr: Option<PrInfo>,
     is_current: bool,
+    /// The remote ref (e.g. `origin/feature`) for branches that only exist
+    /// on a remote.
+    remote_ref: Option<String>,
+    /// Path of another worktree that has this branch checked out, which
+    /// makes `git checkout` refuse it here.
+    worktree: Option<PathBuf>,
+    /// `branch.<name>.description`, as `git branch --edit-description`
+    /// sets it.
+    description: Option<String>,
+    /// When HEAD last moved to this branch, going by HEAD's reflog.
+    last_checkout_timestamp: Option<i64>,
+    /// The tip commit's signature; checking it runs gpg, so it's only
+    /// done with `--verify` or once the branch has been selected.
+    signature: Option<SignatureStatus>,
+}
+
+/// Which refs to list, kept so the list can be re-read in place.
+#[derive(Clone)]
+struct ListOptions {
+    include_tags: bool,
+    /// Compare branches against this instead of the detected default branch.
+    default_branch: Option<String>,
+    /// Count every branch's commits ahead of the default branch up front,
+    /// rather than only as branches are selected.
+    with_stats: bool,
+    hide_current: bool,
+    /// Leave out branches whose last commit is older than this.
+    since: Option<Age>,
+    limit: Option<usize>,
+}
+
+/// `git diff --stat` of a branch running on a background thread.
+struct DiffStatLoad {
+    name: String,
+    width: u16,
+    receiver: mpsc::Receiver<Option<Vec<String>>>,
+}
+
+/// A branch's signature being checked on a background thread.
+struct SignatureCheck {
+    name: String,
+    receiver: mpsc::Receiver<SignatureStatus>,
+}
+
+/// PR info being fetched by `gh` on a background thread.
+struct PrFetch {
+    receiver: mpsc::Receiver<io::Result<HashMap<String, PrInfo>>>,
+    started: Instant,
+}
+
+impl BranchInfo {
+    /// True if the branch has no upstream, or its upstream has been deleted.
+    /// Tags have no upstream to lose.
+    fn is_gone(&self) -> bool {
+        self.ref_type == RefType::Branch
+            && (!self.has_upstream || self.tracking_info.contains("gone"))
+    }
+
+    /// The branch had an upstream, but it has since been deleted.
+    fn is_upstream_gone(&self) -> bool {
+        self.has_upstream && self.tracking_info.contains("gone")
+    }
+
+    fn is_tag(&self) -> bool {
+        self.ref_type == RefType::Tag
+    }
+
+    /// The ref to pass to git commands that read this branch's history.
+    fn rev(&self) -> &str {
+        self.remote_ref.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// What the uncommitted-changes menu goes on to check out.
+enum DirtyCheckout {
+    Selected,
+    /// A commit or ref typed after `c`, checked out detached.
+    Detach(String),
+}
+
+enum InputKind {
+    NewBranch,
+    /// Renames the branch at this index into `branches`.
+    Rename(usize),
+    /// Checks out a commit or ref as a detached HEAD.
+    Detach,
+    /// Sets the description of the branch at this index into `branches`.
+    Describe(usize),
+}
+
+impl InputKind {
+    fn prompt(&self) -> &'static str {
+        match self {
+            InputKind::NewBranch => "New branch",
+            InputKind::Rename(_) => "Rename to",
+            InputKind::Detach => "Checkout commit",
+            InputKind::Describe(_) => "Description",
+        }
+    }
+}
+
+/// Which branches to show by their PR.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PrFilter {
+    All,
+    WithPr,
+    /// Only open PRs that aren't drafts.
+    ReadyForReview,
+}
+
+impl PrFilter {
+    fn next(self) -> Self {
+        match self {
+            PrFilter::All => PrFilter::WithPr,
+            PrFilter::WithPr => PrFilter::ReadyForReview,
+            PrFilter::ReadyForReview => PrFilter::All,
+        }
+    }
+
+    fn matches(self, branch: &BranchInfo) -> bool {
+        match self {
+            PrFilter::All => true,
+            PrFilter::WithPr => branch.pr.is_some(),
+            PrFilter::ReadyForReview => branch
+                .pr
+                .is_some_and(|pr| pr.state == PrState::Open && !pr.is_draft),
+        }
+    }
+}
+
+/// A row of the branch list as drawn.
+enum ListRow<'a> {
+    /// Starts the group of branches with this prefix (`None` for no prefix).
+    Header(Option<&'a str>),
+    /// A position in `App::filtered_indices`.
+    Branch(usize),
+}
+
+/// The part of `name` before the first `/`, if any.
+fn branch_prefix(name: &str) -> Option<&str> {
+    name.split_once('/').map(|(prefix, _)| prefix)
+}
+
+/// Stably reorders `branches` so each prefix group is contiguous, with
+/// groups in order of their first branch.
+fn group_by_prefix(branches: &mut [BranchInfo]) {
+    let mut ranks: HashMap<Option<String>, usize> = HashMap::new();
+    for b in branches.iter() {
+        let next = ranks.len();
+        ranks
+            .entry(branch_prefix(&b.name).map(str::to_string))
+            .or_insert(next);
+    }
+    branches.sort_by_key(|b| ranks[&branch_prefix(&b.name).map(str::to_string)]);
+}
+
+/// A single-line text prompt shown at the bottom of the list.
+struct Input {
+    kind: InputKind,
+    buffer: String,
 }
 
 struct App {
     branches: Vec<BranchInfo>,
+    /// Indices into `branches` that match `search_query`, in display order.
+    filtered_indices: Vec<usize>,
+    /// Selection index into `filtered_indices`.
     state: ListState,
     should_quit: bool,
-    last_checked_out_branch: Option<String>,
-    page_size: usize,
+    /// A branch was checked out (or picked, or a worktree made), which
+    /// decides the exit code.
+    checked_out: bool,
+    last_checked_out_branch: Option<OsString>,
+    /// What the successful checkout printed, shown after the list closes.
+    checkout_output: Vec<std::process::Output>,
+    /// Stash uncommitted changes around the checkout.
+    autostash: bool,
+    stash_pop_failed: bool,
+    /// git's error from the last failed checkout or push, shown above the
+    /// list under the given title.
+    git_error: Option<(&'static str, String)>,
+    use_switch: bool,
+    /// Check out with `-f` and without asking about uncommitted changes.
+    force: bool,
+    /// Words of `--checkout-command`, run to check out branches instead of
+    /// git.
+    checkout_template: Option<Vec<String>>,
+    input: Option<Input>,
+    /// Rows moved by PageUp/PageDown; `None` means a full visible page.
+    page_size: Option<usize>,
+    keys: KeyConfig,
+    theme: Theme,
+    sort_mode: SortMode,
+    search_query: String,
+    searching: bool,
+    /// `--type-to-filter`: the search prompt is always open, so keys type
+    /// into it and only arrows move.
+    type_to_filter: bool,
+    search_mode: SearchMode,
+    /// With `SearchMode::Jump`, positions in `filtered_indices` whose names
+    /// contain `search_query`.
+    search_matches: Vec<usize>,
+    /// The entry of `search_matches` that `n`/`N` last moved to.
+    current_match: usize,
+    /// Treat `search_query` as a regex (`--regex`, or Ctrl-t while searching).
+    regex_search: bool,
+    /// `search_query` compiled, with `regex_search` and a valid query.
+    search_regex: Option<Regex>,
+    /// Why `search_query` isn't a valid regex, shown in the title.
+    regex_error: Option<String>,
+    hide_gone: bool,
+    hide_remotes: bool,
+    pr_filter: PrFilter,
+    /// Show branches under a header per `prefix/`.
+    group_by_prefix: bool,
+    date_format: DateFormat,
+    /// Show each branch's upstream before its ahead/behind counts.
+    verbose: bool,
+    /// Rows show only the name and a tracking arrow, as on narrow terminals.
+    compact: bool,
+    /// Row markers: plain text, or Nerd Font icons with `--icons`.
+    glyphs: &'static Glyphs,
+    /// Rows are numbered, and typing a number then Enter selects that row.
+    line_numbers: bool,
+    /// Digits typed so far towards a row number.
+    number_input: String,
+    /// `next`/`previous` wrap around at the ends instead of stopping.
+    wrap_navigation: bool,
+    /// Older branches left out by `--limit`.
+    hidden_by_limit: usize,
+    list_options: ListOptions,
+    /// Branches pinned in config.toml, then those pinned with `f`; they're
+    /// listed first in this order.
+    pinned_in_config: Vec<String>,
+    pinned: Vec<String>,
+    /// Where per-repository state (like `pinned`) is keyed, and what
+    /// relative worktree paths are relative to.
+    repo_path: PathBuf,
+    /// Where `w` puts new worktrees, with `{branch}` and `{repo}` filled in.
+    worktree_path_template: String,
+    /// The worktree `w` created or found, to report on exit.
+    opened_worktree: Option<PathBuf>,
+    /// Abbreviated commit hash when HEAD isn't on a branch.
+    detached_head: Option<String>,
+    /// How long `gh` gets to answer when PRs are re-fetched; `None` with
+    /// `--no-pr`.
+    gh_timeout: Option<Duration>,
+    /// Print git commands on exit instead of running them.
+    dry_run: bool,
+    /// Print the chosen branch name on exit instead of checking it out.
+    print_only: bool,
+    /// The list is drawn on stderr, see `tui_on_stderr`.
+    tui_on_stderr: bool,
+    dry_run_commands: Vec<Vec<String>>,
+    /// Index into `branches` of the branch awaiting delete confirmation.
+    pending_delete: Option<usize>,
+    /// Set when `git branch -d` refused to delete `pending_delete`
+    /// because it is not fully merged; `D` then force-deletes it.
+    delete_error: Option<String>,
+    status_message: Option<String>,
+    /// Indices into `branches` marked with Space for batch deletion.
+    selected_for_action: HashSet<usize>,
+    pending_batch_delete: bool,
+    show_help: bool,
+    /// Set until the background `gh` query finishes.
+    pr_fetch: Option<PrFetch>,
+    /// At most one at a time, so scrolling past unchecked branches doesn't
+    /// start a gpg for each of them.
+    signature_check: Option<SignatureCheck>,
+    /// PR info is missing because `gh` didn't answer in time.
+    pr_timed_out: bool,
+    /// The working tree has uncommitted changes, so checkouts ask first.
+    is_dirty: bool,
+    /// Entries in `git stash list`, mentioned in the footer as a reminder.
+    stash_count: usize,
+    /// Showing the menu of what to do with uncommitted changes before a
+    /// checkout.
+    pending_dirty_checkout: Option<DirtyCheckout>,
+    /// The menu's "stash" choice: stash like `--autostash`, but leave the
+    /// changes stashed after switching.
+    keep_stash: bool,
+    /// Asking before a remote-only branch gets a local tracking branch.
+    pending_track_checkout: bool,
+    /// Branch to push once "Pushing..." has been drawn, since the push
+    /// holds up the UI until git is done.
+    pending_push: Option<usize>,
+    /// Branch to fast-forward once "Updating..." has been drawn, for the
+    /// same reason.
+    pending_pull: Option<usize>,
+    /// Ref to show in `git log` with the UI suspended, set by `l`.
+    pending_log: Option<String>,
+    /// The list is on the alternate screen rather than inline.
+    fullscreen: bool,
+    /// `git log` output per branch name, so moving the selection doesn't
+    /// re-spawn git on every keystroke.
+    commit_cache: HashMap<String, Vec<String>>,
+    /// The preview shows `git diff --stat` against the default branch
+    /// instead of recent commits, toggled with `v`.
+    show_diff_stat: bool,
+    /// `git diff --stat` output per branch with the width it was fitted
+    /// to, or `None` if there's no default branch to diff against.
+    diff_stat_cache: HashMap<String, (u16, Option<Vec<String>>)>,
+    diff_stat_load: Option<DiffStatLoad>,
+    /// Branches whose `commits_ahead_of_main` has been counted, so it's
+    /// done once per branch.
+    stats_checked: HashSet<String>,
+    /// Where the list was last drawn, for mapping mouse clicks to rows.
+    list_area: Rect,
+    /// Inner width of the preview pane as last drawn, or 0 when the
+    /// terminal is too narrow for it.
+    preview_width: u16,
+    /// Time and row of the previous click, for detecting double-clicks.
+    last_click: Option<(Instant, usize)>,
+    /// When relative dates were last re-formatted, so "2 minutes ago"
+    /// keeps up with a list that stays open.
+    dates_refreshed: Instant,
 }
 
 impl App {
-    fn new(branches: Vec<BranchInfo>, page_size: usize) -> Self {
+    fn new(
+        branches: Vec<BranchInfo>,
+        page_size: Option<usize>,
+        keys: KeyConfig,
+        theme: Theme,
+        sort_mode: SortMode,
+    ) -> Self {
+        let filtered_indices = (0..branches.len()).collect();
         Self {
             branches,
+            filtered_indices,
             state: ListState::default(),
             should_quit: false,
+            checked_out: false,
             last_checked_out_branch: None,
+            checkout_output: Vec::new(),
+            autostash: false,
+            stash_pop_failed: false,
+            git_error: None,
+            use_switch: false,
+            force: false,
+            checkout_template: None,
+            input: None,
             page_size,
+            keys,
+            theme,
+            sort_mode,
+            search_query: String::new(),
+            searching: false,
+            type_to_filter: false,
+            search_mode: SearchMode::Filter,
+            search_matches: Vec::new(),
+            current_match: 0,
+            regex_search: false,
+            search_regex: None,
+            regex_error: None,
+            hide_gone: false,
+            hide_remotes: false,
+            pr_filter: PrFilter::All,
+            group_by_prefix: false,
+            date_format: DateFormat::Relative,
+            verbose: false,
+            compact: false,
+            glyphs: &DEFAULT_GLYPHS,
+            line_numbers: false,
+            number_input: String::new(),
+            wrap_navigation: true,
+            hidden_by_limit: 0,
+            list_options: ListOptions {
+                include_tags: false,
+                default_branch: None,
+                with_stats: false,
+                hide_current: false,
+                since: None,
+                limit: None,
+            },
+            gh_timeout: None,
+            detached_head: None,
+            pinned_in_config: Vec::new(),
+            pinned: Vec::new(),
+            repo_path: PathBuf::new(),
+            worktree_path_template: String::new(),
+            opened_worktree: None,
+            dry_run: false,
+            print_only: false,
+            tui_on_stderr: false,
+            dry_run_commands: Vec::new(),
+            pending_delete: None,
+            delete_error: None,
+            status_message: None,
+            selected_for_action: HashSet::new(),
+            pending_batch_delete: false,
+            show_help: false,
+            pr_fetch: None,
+            signature_check: None,
+            pr_timed_out: false,
+            is_dirty: false,
+            stash_count: 0,
+            pending_dirty_checkout: None,
+            keep_stash: false,
+            pending_track_checkout: false,
+            pending_push: None,
+            pending_pull: None,
+            pending_log: None,
+            fullscreen: false,
+            commit_cache: HashMap::new(),
+            show_diff_stat: false,
+            diff_stat_cache: HashMap::new(),
+            diff_stat_load: None,
+            stats_checked: HashSet::new(),
+            list_area: Rect::default(),
+            preview_width: 0,
+            last_click: None,
+            dates_refreshed: Instant::now(),
         }
     }
 
+    /// True while a confirmation or text prompt is capturing input.
+    fn is_prompting(&self) -> bool {
+        self.pending_delete.is_some()
+            || self.pending_batch_delete
+            || self.input.is_some()
+            || self.show_help
+            || self.pending_dirty_checkout.is_some()
+            || self.pending_track_checkout
+    }
+
+    /// Index into `branches` of the highlighted row.
+    fn selected_index(&self) -> Option<usize> {
+        let i = self.state.selected()?;
+        self.filtered_indices.get(i).copied()
+    }
+
+    fn selected_branch(&self) -> Option<&BranchInfo> {
+        self.selected_index().map(|i| &self.branches[i])
+    }
+
     pub fn next(&mut self) {
+        let Some(last) = self.filtered_indices.len().checked_sub(1) else {
+            return;
+        };
         let i = match self.state.selected() {
             Some(i) => {
-                if i >= self.branches.len() - 1 {
+                if i < last {
+                    i + 1
+                } else if self.wrap_navigation {
                     0
                 } else {
-                    i + 1
+                    i
                 }
             }
             None => 0,
@@ -65,12 +692,18 @@ impl App {
     }
 
     pub fn previous(&mut self) {
+        let Some(last) = self.filtered_indices.len().checked_sub(1) else {
+            return;
+        };
         let i = match self.state.selected() {
             Some(i) => {
-                if i == 0 {
-                    self.branches.len() - 1
+                if i > 0 {
+                    // A stale selection past the end lands on the last row
+                    (i - 1).min(last)
+                } else if self.wrap_navigation {
+                    last
                 } else {
-                    i - 1
+                    0
                 }
             }
             None => 0,
@@ -82,275 +715,3446 @@ impl App {
         self.should_quit = true;
     }
 
+    /// Number of list rows visible inside the borders as of the last draw.
+    fn visible_rows(&self) -> usize {
+        (self.list_area.height.saturating_sub(2) as usize).max(1)
+    }
+
+    fn page_len(&self) -> usize {
+        self.page_size.unwrap_or_else(|| self.visible_rows())
+    }
+
+    /// Moves the selection and the scroll offset down by a page, so the
+    /// cursor stays on the same screen row where possible.
     pub fn next_page(&mut self) {
+        self.scroll_down(self.page_len());
+    }
+
+    pub fn prev_page(&mut self) {
+        self.scroll_up(self.page_len());
+    }
+
+    /// Moves half a page down, like Ctrl-d in vim.
+    fn half_page_down(&mut self) {
+        self.scroll_down((self.page_len() / 2).max(1));
+    }
+
+    /// Moves half a page up, like Ctrl-u in vim.
+    fn half_page_up(&mut self) {
+        self.scroll_up((self.page_len() / 2).max(1));
+    }
+
+    /// Moves the selection and the view down by `page` rows, stopping at
+    /// the last branch.
+    fn scroll_down(&mut self, page: usize) {
+        let Some(last) = self.filtered_indices.len().checked_sub(1) else {
+            return;
+        };
         let i = match self.state.selected() {
-            Some(i) => i
-                .saturating_add(self.page_size)
-                .min(self.branches.len() - 1),
+            Some(i) => i.saturating_add(page).min(last),
             None => 0,
         };
+        let max_offset = self.list_rows().len().saturating_sub(self.visible_rows());
+        let offset = self.state.offset().saturating_add(page).min(max_offset);
+        *self.state.offset_mut() = offset;
         self.state.select(Some(i));
     }
 
-    pub fn prev_page(&mut self) {
+    /// Moves the selection and the view up by `page` rows, stopping at the
+    /// first branch.
+    fn scroll_up(&mut self, page: usize) {
+        let Some(last) = self.filtered_indices.len().checked_sub(1) else {
+            return;
+        };
         let i = match self.state.selected() {
-            Some(i) => i.saturating_sub(self.page_size),
+            Some(i) => i.saturating_sub(page).min(last),
             None => 0,
         };
+        let offset = self.state.offset().saturating_sub(page);
+        *self.state.offset_mut() = offset;
         self.state.select(Some(i));
     }
-}
-
-fn main() -> Result<()> {
-    color_eyre::install()?;
 
-    let branches = get_branch_info()?;
-    let height = if branches.is_empty() {
-        3
-    } else {
-        branches.len().saturating_add(2).min(20) as u16 // 2 for borders, max 20
-    };
+    pub fn first(&mut self) {
+        if !self.filtered_indices.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
 
-    // Terminal initialization for inline rendering
-    enable_raw_mode()?;
-    stdout().execute(cursor::SavePosition)?;
-    let mut terminal = Terminal::with_options(
-        CrosstermBackend::new(stdout()),
-        TerminalOptions {
-            viewport: Viewport::Inline(height),
-        },
-    )?;
+    pub fn last(&mut self) {
+        if let Some(last) = self.filtered_indices.len().checked_sub(1) {
+            self.state.select(Some(last));
+        }
+    }
 
-    let page_size = (height as usize).saturating_sub(2);
-    let mut app = App::new(branches, page_size);
+    /// Selects the row numbered by `number_input`, as shown with
+    /// `--line-numbers`.
+    fn go_to_number(&mut self) {
+        let input = std::mem::take(&mut self.number_input);
+        match input.parse::<usize>() {
+            Ok(n) if (1..=self.filtered_indices.len()).contains(&n) => {
+                self.state.select(Some(n - 1))
+            }
+            _ => self.status_message = Some(format!("No branch number {}", input)),
+        }
+    }
 
-    let initial_selection = app.branches.iter().position(|b| b.is_current).or_else(|| {
-        if app.branches.is_empty() {
-            None
-        } else {
-            Some(0)
+    /// Moves the selection to the checked-out branch.
+    fn select_current(&mut self) {
+        let current = self
+            .filtered_indices
+            .iter()
+            .position(|&i| self.branches[i].is_current);
+        if current.is_some() {
+            self.state.select(current);
+            return;
         }
-    });
+        let reason = if self.branches.iter().any(|b| b.is_current) {
+            "The current branch is hidden by a filter"
+        } else if self.list_options.hide_current {
+            "The current branch is hidden by --hide-current"
+        } else if let Some(hash) = &self.detached_head {
+            &format!("HEAD is detached at {}", hash)
+        } else {
+            "There's no current branch"
+        };
+        self.status_message = Some(reason.to_string());
+    }
 
-    if let Some(selected_index) = initial_selection {
-        app.state.select(Some(selected_index));
+    /// Recomputes `filtered_indices` from `search_query`, keeping the
+    /// selection on the same branch when it is still visible.
+    fn update_filter(&mut self) {
+        let selected_name = self.selected_branch().map(|b| b.name.clone());
+        self.refilter(selected_name.as_deref());
     }
 
-    run_app(&mut terminal, &mut app)?;
+    /// Recomputes `filtered_indices` and selects the branch named `name`,
+    /// falling back to the first visible row.
+    fn refilter(&mut self, name: Option<&str>) {
+        self.update_filtered_indices();
+        let selection = name
+            .and_then(|name| {
+                self.filtered_indices
+                    .iter()
+                    .position(|&i| self.branches[i].name == name)
+            })
+            .or(if self.filtered_indices.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+        self.state.select(selection);
+    }
 
-    // Restore terminal
-    disable_raw_mode()?;
-    stdout().execute(cursor::RestorePosition)?;
-    stdout().execute(cursor::MoveDown(height))?;
+    /// Whether `name` passes the search filter. An invalid regex filters
+    /// nothing, rather than hiding everything while it's being typed.
+    fn query_matches(&self, name: &str) -> bool {
+        match &self.search_regex {
+            Some(regex) => regex.is_match(name),
+            None => self.regex_search || fuzzy_match(&self.search_query, name),
+        }
+    }
 
-    if let Some(branch_name) = app.last_checked_out_branch {
-        // run git checkout <branch_name>
-        // and pipe the output to the parent terminal
-        let mut command = std::process::Command::new("git");
-        command.arg("checkout").arg(branch_name);
-        command.stdout(std::process::Stdio::inherit());
-        command.stderr(std::process::Stdio::inherit());
-        let _ = command.status()?; // We can ignore the result, git will print errors.
+    /// Byte range in `name` of the search query's first match, for jumping
+    /// to and highlighting.
+    fn search_range(&self, name: &str) -> Option<Range<usize>> {
+        match &self.search_regex {
+            Some(regex) => regex
+                .find(name)
+                .map(|m| m.range())
+                .filter(|r| !r.is_empty()),
+            None if self.regex_search => None,
+            None => find_ignore_case(name, &self.search_query),
+        }
     }
 
-    Ok(())
-}
+    /// Compiles `search_query` into `search_regex` when searching by regex.
+    fn compile_search(&mut self) {
+        self.search_regex = None;
+        self.regex_error = None;
+        if !self.regex_search || self.search_query.is_empty() {
+            return;
+        }
+        match RegexBuilder::new(&self.search_query)
+            .case_insensitive(true)
+            .build()
+        {
+            Ok(regex) => self.search_regex = Some(regex),
+            // The last line says what's wrong, e.g. "error: unclosed group"
+            Err(err) => {
+                let err = err.to_string();
+                let reason = err.lines().last().unwrap_or_default();
+                self.regex_error = Some(reason.trim_start_matches("error: ").to_string());
+            }
+        }
+    }
 
-fn get_pr_map() -> io::Result<HashMap<String, u32>> {
-    // Check if gh is installed
-    let version_output = std::process::Command::new("gh").arg("--version").output();
-    if version_output.is_err() {
-        return Ok(HashMap::new()); // gh not found
+    fn toggle_regex_search(&mut self) {
+        self.regex_search = !self.regex_search;
+        self.search_changed();
     }
 
-    let pr_list_output = std::process::Command::new("gh")
-        .args([
-            "pr",
-            "list",
-            "--json",
-            "headRefName,number",
-            "--limit",
-            "1000",
-        ])
-        .output()?;
+    fn matching_indices(&self) -> Vec<usize> {
+        let filter_by_query = self.search_mode == SearchMode::Filter;
+        self.branches
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !(self.hide_gone && b.is_gone()))
+            .filter(|(_, b)| !(self.hide_remotes && b.remote_ref.is_some()))
+            .filter(|(_, b)| self.pr_filter.matches(b))
+            .filter(|(_, b)| !filter_by_query || self.query_matches(&b.name))
+            .map(|(i, _)| i)
+            .collect()
+    }
 
-    if !pr_list_output.status.success() {
-        return Ok(HashMap::new()); // e.g. not a gh repository
+    /// Recomputes `filtered_indices`, and `search_matches` with them.
+    fn update_filtered_indices(&mut self) {
+        self.compile_search();
+        self.filtered_indices = self.matching_indices();
+        self.search_matches = if self.search_mode == SearchMode::Jump {
+            self.filtered_indices
+                .iter()
+                .enumerate()
+                .filter(|&(_, &i)| self.search_range(&self.branches[i].name).is_some())
+                .map(|(pos, _)| pos)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.current_match = 0;
     }
 
-    let prs: Vec<PullRequest> = match serde_json::from_slice(&pr_list_output.stdout) {
-        Ok(prs) => prs,
-        Err(_) => return Ok(HashMap::new()), // JSON parsing failed
-    };
+    /// Updates the matches for a changed query and moves to the first.
+    fn search_jump(&mut self) {
+        self.update_filter();
+        if let Some(&first) = self.search_matches.first() {
+            self.state.select(Some(first));
+        }
+    }
 
-    let pr_map = prs
-        .into_iter()
-        .map(|pr| (pr.headRefName, pr.number))
-        .collect();
+    /// Moves to the next (or with `backward`, previous) match, wrapping
+    /// around at the ends.
+    fn cycle_match(&mut self, backward: bool) {
+        let len = self.search_matches.len();
+        if len == 0 {
+            return;
+        }
+        self.current_match = if backward {
+            (self.current_match + len - 1) % len
+        } else {
+            (self.current_match + 1) % len
+        };
+        self.state
+            .select(Some(self.search_matches[self.current_match]));
+    }
 
-    Ok(pr_map)
-}
+    /// Removes `branches[index]`, keeping the selection at the same row
+    /// (clamped to the end of the list).
+    fn remove_branch(&mut self, index: usize) {
+        let selected = self.state.selected();
+        self.branches.remove(index);
+        self.selected_for_action = self
+            .selected_for_action
+            .iter()
+            .filter(|&&i| i != index)
+            .map(|&i| if i > index { i - 1 } else { i })
+            .collect();
+        self.update_filtered_indices();
+        let selection = match self.filtered_indices.len() {
+            0 => None,
+            len => Some(selected.unwrap_or(0).min(len - 1)),
+        };
+        self.state.select(selection);
+    }
+
+    /// Selects the visible row for `branches[index]`, or the closest
+    /// visible branch after it (or the last one) if it is hidden.
+    fn select_nearest(&mut self, index: Option<usize>) {
+        let selection = match self.filtered_indices.len() {
+            0 => None,
+            len => Some(
+                index
+                    .map(|b| self.filtered_indices.partition_point(|&i| i < b))
+                    .unwrap_or(0)
+                    .min(len - 1),
+            ),
+        };
+        self.state.select(selection);
+    }
 
-fn get_branch_info() -> io::Result<Vec<BranchInfo>> {
-    let pr_map = get_pr_map().unwrap_or_default();
-    const DELIMITER: &str = "|";
+    fn toggle_hide_gone(&mut self) {
+        self.hide_gone = !self.hide_gone;
+        self.refilter_nearest();
+    }
 
-    // 1. Get local branches and populate the map
-    let local_format = [
-        "%(HEAD)",
-        "%(refname:short)",
-        "%(upstream:track,nobracket)",
-        "%(committerdate:relative)",
-        "%(committerdate:unix)",
-        "%(upstream:short)",
-    ]
-    .join(DELIMITER);
+    fn toggle_hide_remotes(&mut self) {
+        self.hide_remotes = !self.hide_remotes;
+        self.refilter_nearest();
+    }
 
-    let local_output = std::process::Command::new("git")
-        .args(["for-each-ref", &format!("--format={}", local_format), "refs/heads/"])
-        .output()?;
+    fn cycle_pr_filter(&mut self) {
+        self.pr_filter = self.pr_filter.next();
+        self.refilter_nearest();
+    }
 
-    if !local_output.status.success() {
-        let stderr = String::from_utf8_lossy(&local_output.stderr);
-        return Err(io::Error::new(io::ErrorKind::Other, stderr.to_string()));
-    }
-
-    let local_stdout = String::from_utf8_lossy(&local_output.stdout);
-    let mut branches_map: HashMap<String, BranchInfo> = local_stdout
-        .lines()
-        .filter_map(|line| {
-            let parts: Vec<&str> = line.split(DELIMITER).collect();
-            if parts.len() == 6 {
-                let is_current = !parts[0].trim().is_empty();
-                let branch_name = parts[1].to_string();
-                let timestamp = parts[4].parse::<i64>().unwrap_or(0);
-                let has_upstream = !parts[5].trim().is_empty();
-                let pr_number = pr_map.get(&branch_name).copied();
-
-                let info = BranchInfo {
-                    name: branch_name.clone(),
-                    tracking_info: parts[2].to_string(),
-                    last_commit_date: parts[3].to_string(),
-                    last_commit_timestamp: timestamp,
-                    has_upstream,
-                    pr_number,
-                    is_current,
-                };
-                Some((branch_name, info))
-            } else {
-                None
+    fn toggle_group_by_prefix(&mut self) {
+        self.group_by_prefix = !self.group_by_prefix;
+        self.resort();
+    }
+
+    /// The rows as drawn: `filtered_indices` with a header in front of each
+    /// group when grouping by prefix.
+    fn list_rows(&self) -> Vec<ListRow<'_>> {
+        let mut rows = Vec::with_capacity(self.filtered_indices.len());
+        let mut current_group = None;
+        for (pos, &i) in self.filtered_indices.iter().enumerate() {
+            if self.group_by_prefix {
+                let group = branch_prefix(&self.branches[i].name);
+                if current_group != Some(group) {
+                    rows.push(ListRow::Header(group));
+                    current_group = Some(group);
+                }
             }
-        })
-        .collect();
+            rows.push(ListRow::Branch(pos));
+        }
+        rows
+    }
 
-    // 2. Get remote branches and merge/update info
-    let remote_format = [
-        "%(refname)",
-        "%(refname:short)",
-        "%(committerdate:relative)",
-        "%(committerdate:unix)",
-    ]
-    .join(DELIMITER);
+    /// Recomputes `filtered_indices` after a filter toggle, keeping the
+    /// selection on the same or nearest branch.
+    fn refilter_nearest(&mut self) {
+        let selected = self.selected_index();
+        self.update_filtered_indices();
+        self.select_nearest(selected);
+    }
 
-    let remote_output = std::process::Command::new("git")
-        .args(["for-each-ref", &format!("--format={}", remote_format), "refs/remotes/"])
-        .output()?;
+    fn request_delete(&mut self) {
+        if let Some(index) = self.selected_index() {
+            if self.branches[index].is_tag() {
+                self.status_message = Some("Can't delete a tag".to_string());
+                return;
+            }
+            self.pending_delete = Some(index);
+            self.delete_error = None;
+        }
+    }
 
-    if remote_output.status.success() {
-        let remote_stdout = String::from_utf8_lossy(&remote_output.stdout);
-        remote_stdout.lines().for_each(|line| {
-            let parts: Vec<&str> = line.split(DELIMITER).collect();
-            if parts.len() == 4 {
-                let full_ref_name = parts[0].trim();
-                if full_ref_name.ends_with("/HEAD") {
-                    return;
-                }
+    fn cancel_delete(&mut self) {
+        self.pending_delete = None;
+        self.delete_error = None;
+    }
 
-                let remote_ref_name = parts[1].trim();
-                let short_name = match remote_ref_name.split_once('/') {
-                    Some((_, branch)) => branch,
-                    None => remote_ref_name,
-                };
+    fn confirm_delete(&mut self, force: bool) {
+        let Some(index) = self.pending_delete else {
+            return;
+        };
+        let name = self.branches[index].name.clone();
+        match self.git(&["branch", if force { "-D" } else { "-d" }, &name]) {
+            Ok(()) => {
+                self.remove_branch(index);
+                self.status_message = Some(format!("Deleted branch '{}'", name));
+                self.cancel_delete();
+            }
+            Err(err) if !force && err.contains("not fully merged") => {
+                self.delete_error = Some(err);
+            }
+            Err(err) => {
+                self.status_message = Some(err);
+                self.cancel_delete();
+            }
+        }
+    }
 
-                let remote_last_commit_date = parts[2].to_string();
-                let remote_timestamp = parts[3].parse::<i64>().unwrap_or(0);
+    /// Re-sorts `branches` by `sort_mode`, keeping the selection on the
+    /// same branch.
+    fn resort(&mut self) {
+        let selected_name = self.selected_branch().map(|b| b.name.clone());
+        let marked: HashSet<String> = self
+            .selected_for_action
+            .iter()
+            .map(|&i| self.branches[i].name.clone())
+            .collect();
+        self.sort_mode.sort(&mut self.branches);
+        self.pin_to_top();
+        if self.group_by_prefix {
+            group_by_prefix(&mut self.branches);
+        }
+        self.selected_for_action = self
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| marked.contains(&b.name))
+            .map(|(i, _)| i)
+            .collect();
+        self.refilter(selected_name.as_deref());
+    }
 
-                if let Some(existing_branch) = branches_map.get_mut(short_name) {
-                    // Branch exists locally, update if remote is newer
-                    if remote_timestamp > existing_branch.last_commit_timestamp {
-                        existing_branch.last_commit_timestamp = remote_timestamp;
-                        existing_branch.last_commit_date = remote_last_commit_date;
-                    }
-                } else {
-                    // Branch is remote-only, add it.
-                    let pr_number = pr_map.get(short_name).copied();
-                    let info = BranchInfo {
-                        name: short_name.to_string(),
-                        tracking_info: "remote".to_string(),
-                        last_commit_date: remote_last_commit_date,
-                        last_commit_timestamp: remote_timestamp,
-                        has_upstream: true, // It is an upstream branch
-                        pr_number,
-                        is_current: false,
-                    };
-                    branches_map.insert(short_name.to_string(), info);
-                }
+    /// Where `name` goes among the pinned branches, if it's pinned.
+    fn pin_rank(&self, name: &str) -> Option<usize> {
+        self.pinned_in_config
+            .iter()
+            .chain(&self.pinned)
+            .position(|pinned| pinned == name)
+    }
+
+    /// Stably moves pinned branches to the front, in pin order.
+    fn pin_to_top(&mut self) {
+        if self.pinned_in_config.is_empty() && self.pinned.is_empty() {
+            return;
+        }
+        let mut branches = std::mem::take(&mut self.branches);
+        branches.sort_by_key(|b| {
+            match b.ref_type {
+                RefType::Branch => self.pin_rank(&b.name),
+                RefType::Tag => None,
             }
+            .unwrap_or(usize::MAX)
         });
+        self.branches = branches;
     }
 
-    let mut branches: Vec<BranchInfo> = branches_map.into_values().collect();
-    branches.sort_by(|a, b| b.last_commit_timestamp.cmp(&a.last_commit_timestamp));
+    /// Pins or unpins the highlighted branch, remembering it for this
+    /// repository.
+    fn toggle_pin(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        if branch.is_tag() {
+            self.status_message = Some("Can't pin a tag".to_string());
+            return;
+        }
+        let name = branch.name.clone();
+        if self.pinned_in_config.contains(&name) {
+            self.status_message = Some(format!("'{}' is pinned in config.toml", name));
+            return;
+        }
+        let message = match self.pinned.iter().position(|pinned| *pinned == name) {
+            Some(pos) => {
+                self.pinned.remove(pos);
+                format!("Unpinned '{}'", name)
+            }
+            None => {
+                self.pinned.push(name.clone());
+                format!("Pinned '{}'", name)
+            }
+        };
+        self.status_message = Some(match state::save_pinned(&self.repo_path, &self.pinned) {
+            Ok(()) => message,
+            Err(err) => format!("{}, but couldn't save it: {}", message, err),
+        });
+        self.resort();
+    }
 
-    Ok(branches)
-}
+    /// Toggles the highlighted branch's mark for batch deletion. The current
+    /// branch and remote-only branches can't be deleted, so can't be marked.
+    fn toggle_mark(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        if branch.is_current {
+            self.status_message = Some("Can't mark the current branch".to_string());
+        } else if branch.remote_ref.is_some() {
+            self.status_message = Some("Can't mark a remote-only branch".to_string());
+        } else if branch.is_tag() {
+            self.status_message = Some("Can't mark a tag".to_string());
+        } else if !self.selected_for_action.remove(&index) {
+            self.selected_for_action.insert(index);
+        }
+    }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> io::Result<()> {
-    while !app.should_quit {
-        terminal.draw(|f| ui(f, app))?;
-        handle_events(app)?;
+    fn request_batch_delete(&mut self) {
+        if self.selected_for_action.is_empty() {
+            self.status_message = Some("No branches marked (mark with Space)".to_string());
+        } else {
+            self.pending_batch_delete = true;
+        }
     }
-    Ok(())
-}
 
-fn handle_events(app: &mut App) -> io::Result<()> {
-    if let Event::Key(key) = event::read()? {
-        match key.code {
-            KeyCode::Char('q') => app.quit(),
-            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => app.quit(),
-            KeyCode::Down | KeyCode::Char('j') => app.next(),
-            KeyCode::Up | KeyCode::Char('k') => app.previous(),
-            KeyCode::PageDown => app.next_page(),
-            KeyCode::PageUp => app.prev_page(),
-            KeyCode::Enter => {
-                if let Some(selected) = app.state.selected() {
-                    app.last_checked_out_branch = Some(app.branches[selected].name.clone());
+    /// Force-deletes every marked branch, reporting any that git refused.
+    fn confirm_batch_delete(&mut self) {
+        self.pending_batch_delete = false;
+        let mut marked: Vec<usize> = self.selected_for_action.drain().collect();
+        // Delete from the back so earlier indices stay valid
+        marked.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut deleted = 0;
+        let mut failures = Vec::new();
+        for index in marked {
+            let name = self.branches[index].name.clone();
+            match self.git(&["branch", "-D", &name]) {
+                Ok(()) => {
+                    self.remove_branch(index);
+                    deleted += 1;
                 }
-                app.quit();
+                Err(err) => failures.push(format!(
+                    "{} ({})",
+                    name,
+                    err.lines().next().unwrap_or_default()
+                )),
             }
-            _ => {}
         }
+        self.status_message = Some(if failures.is_empty() {
+            format!("Deleted {} branches", deleted)
+        } else {
+            format!("Deleted {}, failed: {}", deleted, failures.join(", "))
+        });
     }
-    Ok(())
-}
 
-fn ui(f: &mut Frame, app: &mut App) {
-    if app.branches.is_empty() {
-        let text = "No git branches found in this directory.";
-        let block = Block::default().title("Error").borders(Borders::ALL);
-        let paragraph = ratatui::widgets::Paragraph::new(text).block(block);
-        f.render_widget(paragraph, f.area());
-        return;
+    /// The subcommand and create-branch flag to check out with; `git switch`
+    /// spells `checkout -b` as `switch -c`.
+    fn checkout_command(&self) -> (&'static str, &'static str) {
+        if self.use_switch {
+            ("switch", "-c")
+        } else {
+            ("checkout", "-b")
+        }
+    }
+
+    /// Checks out the highlighted branch, or just picks it under `--print`.
+    fn checkout_selected(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        let name = branch.raw_name.clone();
+        let args = match &branch.remote_ref {
+            _ if branch.is_tag() => {
+                vec!["--detach".into(), join_os("refs/tags/", &name), "--".into()]
+            }
+            // Remote-only: create a local tracking branch
+            Some(remote_ref) => {
+                let remote = remote_ref.split_once('/').map_or("", |(remote, _)| remote);
+                vec![
+                    self.checkout_command().1.into(),
+                    name.clone(),
+                    "--track".into(),
+                    join_os(&format!("{}/", remote), &name),
+                ]
+            }
+            None => vec![name.clone()],
+        };
+        // Tags are checked out detached, which a wrapper might not expect
+        let custom = match &self.checkout_template {
+            Some(template) if !branch.is_tag() => Some(expand_checkout_command(template, &name)),
+            _ => None,
+        };
+        self.last_checked_out_branch = Some(name);
+        if self.print_only {
+            self.checked_out = true;
+            self.quit();
+        } else if let Some(command) = custom {
+            self.run_checkout_command(command);
+        } else {
+            self.run_checkout(args);
+        }
+    }
+
+    /// Runs `git checkout <args>` (or `git switch`) with the list still up.
+    /// On success quits, keeping git's output to show once the terminal is
+    /// restored; on failure shows git's error so another branch can be
+    /// picked. With `--autostash`, uncommitted changes are stashed first and
+    /// popped afterwards. With `--force`, local changes git would refuse to
+    /// overwrite are thrown away instead.
+    fn run_checkout(&mut self, args: Vec<OsString>) {
+        let mut command: Vec<OsString> = vec!["git".into(), self.checkout_command().0.into()];
+        if self.force {
+            command.push("-f".into());
+        }
+        command.extend(args);
+        self.run_checkout_command(command);
+    }
+
+    /// Runs `command` (a program and its arguments) to check out a branch,
+    /// as described for `run_checkout`.
+    fn run_checkout_command(&mut self, command: Vec<OsString>) {
+        let stash = (self.autostash || self.keep_stash) && self.is_dirty;
+        let pop = stash && !self.keep_stash;
+        if self.dry_run {
+            if stash {
+                self.dry_run_commands
+                    .push(["git", "stash", "push"].map(String::from).to_vec());
+            }
+            self.dry_run_commands.push(
+                command
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect(),
+            );
+            if pop {
+                self.dry_run_commands
+                    .push(["git", "stash", "pop"].map(String::from).to_vec());
+            }
+            self.checked_out = true;
+            self.quit();
+            return;
+        }
+
+        if stash {
+            match git_output(&["stash", "push", "--message", "git-checkoutui autostash"]) {
+                // Say where the changes went if they're staying there
+                Ok(output) if output.status.success() && !pop => self.checkout_output.push(output),
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    self.keep_stash = false;
+                    self.git_error = Some(("Checkout failed", error_text(&output)));
+                    return;
+                }
+                Err(err) => {
+                    self.keep_stash = false;
+                    self.git_error = Some(("Checkout failed", err.to_string()));
+                    return;
+                }
+            }
+        }
+
+        let output = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(std::process::Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                self.checkout_output.push(output);
+                if pop {
+                    // A failed pop (e.g. conflicts) leaves the changes in
+                    // the stash; say so on exit rather than here
+                    match git_output(&["stash", "pop"]) {
+                        Ok(output) => {
+                            self.stash_pop_failed = !output.status.success();
+                            self.checkout_output.push(output);
+                        }
+                        Err(_) => self.stash_pop_failed = true,
+                    }
+                }
+                self.checked_out = true;
+                self.quit();
+            }
+            result => {
+                let err = match result {
+                    Ok(output) => error_text(&output),
+                    Err(err) => format!("{}: {}", command[0].to_string_lossy(), err),
+                };
+                self.git_error = Some(("Checkout failed", err));
+                self.keep_stash = false;
+                if stash && !git_output(&["stash", "pop"]).is_ok_and(|o| o.status.success()) {
+                    self.status_message =
+                        Some("Couldn't restore stashed changes; see git stash list".to_string());
+                }
+            }
+        }
+    }
+
+    /// Runs `git <args>`, or under `--dry-run` just records it to be printed
+    /// on exit and pretends it worked.
+    fn git(&mut self, args: &[&str]) -> Result<(), String> {
+        if self.dry_run {
+            let mut command = vec!["git".to_string()];
+            command.extend(args.iter().map(|arg| arg.to_string()));
+            self.dry_run_commands.push(command);
+            Ok(())
+        } else {
+            run_git(args)
+        }
+    }
+
+    fn cycle_sort(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        self.resort();
+    }
+
+    /// Switches how dates are shown, re-formatting every branch's date.
+    fn set_date_format(&mut self, date_format: DateFormat) {
+        self.date_format = date_format;
+        let now = unix_now();
+        for b in &mut self.branches {
+            b.last_commit_date =
+                date_format.format(b.last_commit_timestamp, b.last_commit_offset, now);
+        }
+    }
+
+    fn cycle_date_format(&mut self) {
+        self.set_date_format(self.date_format.next());
+    }
+
+    /// Re-formats relative dates once `DATE_REFRESH_INTERVAL` has passed.
+    fn refresh_stale_dates(&mut self) {
+        if self.dates_refreshed.elapsed() < DATE_REFRESH_INTERVAL {
+            return;
+        }
+        self.dates_refreshed = Instant::now();
+        if self.date_format == DateFormat::Relative {
+            self.set_date_format(DateFormat::Relative);
+        }
+    }
+
+    /// Starts `git diff --stat` of the highlighted branch against the
+    /// default branch when the preview shows it and it hasn't been fitted
+    /// to the preview's width yet; `poll_diff_stat` picks up the result.
+    fn load_selected_diff_stat(&mut self) {
+        if !self.show_diff_stat || self.preview_width == 0 || self.diff_stat_load.is_some() {
+            return;
+        }
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        let width = self.preview_width;
+        if self
+            .diff_stat_cache
+            .get(branch.rev())
+            .is_some_and(|(cached_width, _)| *cached_width == width)
+        {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let rev = branch.rev().to_string();
+        let default_branch = self.list_options.default_branch.clone();
+        std::thread::spawn({
+            let rev = rev.clone();
+            move || {
+                let stat = Repository::open_from_env()
+                    .ok()
+                    .and_then(|repo| default_branch_ref(&repo, default_branch.as_deref()))
+                    .map(|base| get_diff_stat(&base, &rev, width));
+                // The receiver is gone if the user already quit
+                let _ = sender.send(stat);
+            }
+        });
+        self.diff_stat_load = Some(DiffStatLoad {
+            name: rev,
+            width,
+            receiver,
+        });
+    }
+
+    /// Caches the diff stat once its background `git diff` has finished.
+    fn poll_diff_stat(&mut self) {
+        let Some(load) = &self.diff_stat_load else {
+            return;
+        };
+        let stat = match load.receiver.try_recv() {
+            Ok(stat) => stat,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Vec::new()),
+        };
+        self.diff_stat_cache
+            .insert(load.name.clone(), (load.width, stat));
+        self.diff_stat_load = None;
+    }
+
+    /// Reads the highlighted branch's recent commits for the preview the
+    /// first time it's selected. Returns whether there's anything new to
+    /// draw.
+    fn load_selected_commits(&mut self) -> bool {
+        if self.preview_width == 0 || self.show_diff_stat {
+            return false;
+        }
+        let Some(branch) = self.selected_branch() else {
+            return false;
+        };
+        if self.commit_cache.contains_key(branch.rev()) {
+            return false;
+        }
+        let rev = branch.rev().to_string();
+        let commits = get_recent_commits(&rev);
+        self.commit_cache.insert(rev, commits);
+        true
+    }
+
+    /// Counts the highlighted branch's commits ahead of the default branch
+    /// the first time it's selected. Returns whether there's anything new
+    /// to draw.
+    fn load_selected_stats(&mut self) -> bool {
+        if self.list_options.with_stats {
+            return false;
+        }
+        let Some(index) = self.selected_index() else {
+            return false;
+        };
+        if !self.stats_checked.insert(self.branches[index].name.clone()) {
+            return false;
+        }
+        let Ok(repo) = Repository::open_from_env() else {
+            return false;
+        };
+        let default_branch = self.list_options.default_branch.as_deref();
+        compare_to_default(
+            &repo,
+            &mut self.branches[index..=index],
+            default_branch,
+            true,
+        );
+        true
+    }
+
+    /// Starts checking the highlighted branch's signature the first time
+    /// it's selected, unless another check is still running;
+    /// `poll_signature` picks up the result.
+    fn load_selected_signature(&mut self) {
+        if self.signature_check.is_some() {
+            return;
+        }
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        if branch.signature.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let rev = branch.rev().to_string();
+        std::thread::spawn(move || {
+            // The receiver is gone if the user already quit
+            let _ = sender.send(commit_signature(&rev));
+        });
+        self.signature_check = Some(SignatureCheck {
+            name: branch.name.clone(),
+            receiver,
+        });
+    }
+
+    /// Marks the branch once its background signature check has finished.
+    fn poll_signature(&mut self) {
+        let Some(check) = &self.signature_check else {
+            return;
+        };
+        let status = match check.receiver.try_recv() {
+            Ok(status) => status,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => SignatureStatus::Unsigned,
+        };
+        // The list may have been reloaded meanwhile
+        if let Some(branch) = self.branches.iter_mut().find(|b| b.name == check.name) {
+            branch.signature = Some(status);
+        }
+        self.signature_check = None;
+    }
+
+    fn open_input(&mut self, kind: InputKind) {
+        self.input = Some(Input {
+            kind,
+            buffer: String::new(),
+        });
+    }
+
+    /// Opens the rename prompt prefilled with the highlighted branch's name.
+    fn open_rename(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        if branch.remote_ref.is_some() {
+            self.status_message = Some("Can't rename a remote-only branch".to_string());
+            return;
+        }
+        if branch.is_tag() {
+            self.status_message = Some("Can't rename a tag".to_string());
+            return;
+        }
+        self.input = Some(Input {
+            kind: InputKind::Rename(index),
+            buffer: branch.name.clone(),
+        });
+    }
+
+    fn rename(&mut self, index: usize, new_name: &str) {
+        let old_name = self.branches[index].name.clone();
+        if old_name == new_name {
+            return;
+        }
+        match self.git(&["branch", "-m", &old_name, new_name]) {
+            Ok(()) => {
+                self.commit_cache.remove(&old_name);
+                self.diff_stat_cache.remove(&old_name);
+                let branch = &mut self.branches[index];
+                branch.name = new_name.to_string();
+                branch.raw_name = new_name.into();
+                self.status_message = Some(format!("Renamed '{}' to '{}'", old_name, new_name));
+                self.resort();
+            }
+            Err(err) => self.status_message = Some(err),
+        }
+    }
+
+    fn open_describe(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        if branch.remote_ref.is_some() {
+            self.status_message = Some("Can't describe a remote-only branch".to_string());
+            return;
+        }
+        if branch.is_tag() {
+            self.status_message = Some("Can't describe a tag".to_string());
+            return;
+        }
+        // The input is a single line, so a multi-line description is
+        // joined up for editing
+        let buffer = branch
+            .description
+            .as_deref()
+            .map(|d| d.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        self.input = Some(Input {
+            kind: InputKind::Describe(index),
+            buffer,
+        });
+    }
+
+    /// Saves `description` to the branch's config, or removes it if empty.
+    fn describe(&mut self, index: usize, description: &str) {
+        let name = self.branches[index].name.clone();
+        let key = format!("branch.{}.description", name);
+        let result = if !description.is_empty() {
+            self.git(&["config", &key, description])
+        } else if self.branches[index].description.is_some() {
+            self.git(&["config", "--unset", &key])
+        } else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                self.branches[index].description =
+                    (!description.is_empty()).then(|| description.to_string());
+                self.status_message = Some(if description.is_empty() {
+                    format!("Removed the description of '{}'", name)
+                } else {
+                    format!("Described '{}'", name)
+                });
+            }
+            Err(err) => self.status_message = Some(err),
+        }
+    }
+
+    fn submit_input(&mut self) {
+        let Some(input) = self.input.take() else {
+            return;
+        };
+        let value = input.buffer.trim();
+        // An empty description clears it
+        if value.is_empty() && !matches!(input.kind, InputKind::Describe(_)) {
+            return;
+        }
+        match input.kind {
+            InputKind::NewBranch => {
+                let (_, create_flag) = self.checkout_command();
+                self.run_checkout(vec![create_flag.into(), value.into()]);
+            }
+            InputKind::Rename(index) => self.rename(index, value),
+            InputKind::Detach if self.is_dirty && !self.autostash && !self.force => {
+                self.pending_dirty_checkout = Some(DirtyCheckout::Detach(value.to_string()));
+            }
+            InputKind::Detach => self.detach(value),
+            InputKind::Describe(index) => self.describe(index, value),
+        }
+    }
+
+    /// Checks out `rev` as a detached HEAD.
+    fn detach(&mut self, rev: &str) {
+        // git reports unknown refs itself; `--` keeps it from trying `rev`
+        // as a path
+        self.run_checkout(vec!["--detach".into(), rev.into(), "--".into()]);
+    }
+
+    /// Goes on with the checkout the uncommitted-changes menu was opened for.
+    fn resume_dirty_checkout(&mut self, checkout: DirtyCheckout) {
+        match checkout {
+            DirtyCheckout::Selected => self.checkout_selected(),
+            DirtyCheckout::Detach(rev) => self.detach(&rev),
+        }
+    }
+
+    fn open_pr(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        self.status_message = Some(match branch.pr {
+            Some(pr) => match open_pr_in_browser(pr) {
+                Ok(()) => format!("Opened {}{} in browser", pr.provider.sigil(), pr.number),
+                Err(err) => err,
+            },
+            None => format!("No PR for '{}'", branch.name),
+        });
+    }
+
+    /// Merges PR info into the list once the background query has finished.
+    fn poll_prs(&mut self) {
+        let Some(fetch) = &self.pr_fetch else {
+            return;
+        };
+        let result = match fetch.receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            // The thread panicked, which just means no PR info
+            Err(mpsc::TryRecvError::Disconnected) => Ok(HashMap::new()),
+        };
+        self.pr_fetch = None;
+        self.pr_timed_out = matches!(&result, Err(e) if e.kind() == io::ErrorKind::TimedOut);
+        let pr_map = result.unwrap_or_default();
+        for branch in self.branches.iter_mut().filter(|b| !b.is_tag()) {
+            branch.pr = pr_map.get(&branch.name).copied();
+        }
+        if self.pr_filter != PrFilter::All {
+            self.refilter_nearest();
+        }
+    }
+
+    /// Fast-forwards the highlighted branch once "Updating..." is on screen,
+    /// if it has an upstream to pull from.
+    fn request_pull(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        let name = &branch.name;
+        self.status_message = Some(if branch.is_tag() {
+            "Can't pull a tag".to_string()
+        } else if branch.remote_ref.is_some() {
+            format!("'{}' only exists on the remote", name)
+        } else if branch.is_gone() {
+            format!("'{}' has no upstream to pull from", name)
+        } else {
+            self.pending_pull = Some(index);
+            format!("Updating '{}'…", name)
+        });
+    }
+
+    /// Fast-forwards a branch to its upstream: `git pull --ff-only` for
+    /// HEAD, otherwise a fetch straight into the branch so it doesn't have
+    /// to be checked out.
+    fn pull_branch(&mut self, index: usize) {
+        let branch = &self.branches[index];
+        let name = branch.name.clone();
+        let result = if branch.is_current {
+            self.git(&["pull", "--ff-only"])
+        } else {
+            match (
+                git_config(&format!("branch.{}.remote", name)),
+                git_config(&format!("branch.{}.merge", name)),
+            ) {
+                // Without `+`, fetch refuses anything but a fast-forward
+                (Some(remote), Some(merge)) => {
+                    self.git(&["fetch", &remote, &format!("{}:refs/heads/{}", merge, name)])
+                }
+                _ => Err(format!("'{}' has no upstream to pull from", name)),
+            }
+        };
+        match result {
+            Ok(()) => {
+                if !self.dry_run {
+                    self.refresh_branch(index);
+                }
+                self.status_message = Some(format!("Updated '{}'", name));
+            }
+            Err(err) => {
+                // The last line says why, e.g. "! [rejected] ... (non-fast-forward)"
+                let reason = err
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.status_message = Some(format!("Couldn't update '{}': {}", name, reason));
+            }
+        }
+    }
+
+    /// Pushes a branch that has no upstream yet to `origin`, making that its
+    /// upstream.
+    fn request_push(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let branch = &self.branches[index];
+        let name = &branch.name;
+        self.status_message = Some(if branch.is_tag() {
+            "Can't push a tag".to_string()
+        } else if branch.remote_ref.is_some() {
+            format!("'{}' is already on the remote", name)
+        } else if let Some(upstream) = branch.upstream.as_deref().filter(|_| branch.has_upstream) {
+            format!("'{}' already has an upstream ({})", name, upstream)
+        } else {
+            self.pending_push = Some(index);
+            format!("Pushing '{}' to origin...", name)
+        });
+    }
+
+    fn push_branch(&mut self, index: usize) {
+        let name = self.branches[index].name.clone();
+        match self.git(&["push", "--set-upstream", "origin", &name]) {
+            Ok(()) => {
+                if !self.dry_run {
+                    self.refresh_branch(index);
+                }
+                self.status_message = Some(format!("Pushed '{}' to origin", name));
+            }
+            Err(err) => {
+                self.status_message = None;
+                self.git_error = Some(("Push failed", err.replace('\t', "    ")));
+            }
+        }
+    }
+
+    /// Re-reads a local branch's commit and tracking info after it moved.
+    fn refresh_branch(&mut self, index: usize) {
+        let branch = &mut self.branches[index];
+        let Ok(repo) = Repository::open_from_env() else {
+            return;
+        };
+        let Ok(Some(info)) = repo
+            .find_branch(&branch.name, BranchType::Local)
+            .and_then(|b| local_branch_info(&repo, &b, unix_now()))
+        else {
+            return;
+        };
+        branch.tracking_info = info.tracking_info;
+        branch.has_upstream = info.has_upstream;
+        branch.upstream = info.upstream;
+        branch.last_commit_timestamp = info.last_commit_timestamp;
+        branch.last_commit_offset = info.last_commit_offset;
+        branch.last_commit_date = self.date_format.format(
+            info.last_commit_timestamp,
+            info.last_commit_offset,
+            unix_now(),
+        );
+        branch.last_author = info.last_author;
+        branch.last_subject = info.last_subject;
+        branch.commits_ahead_of_main = None;
+        // The tip has moved, so its signature needs checking again
+        branch.signature = None;
+        compare_to_default(
+            &repo,
+            std::slice::from_mut(branch),
+            self.list_options.default_branch.as_deref(),
+            self.list_options.with_stats,
+        );
+        if !self.list_options.with_stats {
+            self.stats_checked.remove(&branch.name);
+        }
+        self.commit_cache.remove(&branch.name);
+        self.diff_stat_cache.remove(&branch.name);
+    }
+
+    /// Re-reads branches from the repository and PRs from `gh`, keeping
+    /// the selection and marks on the same branches.
+    fn refresh(&mut self) {
+        let result = Repository::open_from_env().and_then(|repo| {
+            let loaded = load_branches(&repo, self.sort_mode, &self.list_options)?;
+            Ok((loaded, detached_head(&repo)))
+        });
+        let ((mut branches, hidden_by_limit), detached) = match result {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.status_message = Some(format!("Couldn't refresh: {}", err.message()));
+                return;
+            }
+        };
+        let selected_name = self.selected_branch().map(|b| b.name.clone());
+        let marked: HashSet<&str> = self
+            .selected_for_action
+            .iter()
+            .map(|&i| self.branches[i].name.as_str())
+            .collect();
+        // Keep showing the PRs already known until the new query finishes
+        let prs: HashMap<&str, PrInfo> = self
+            .branches
+            .iter()
+            .filter(|b| !b.is_tag())
+            .filter_map(|b| Some((b.name.as_str(), b.pr?)))
+            .collect();
+        for b in branches.iter_mut().filter(|b| !b.is_tag()) {
+            b.pr = prs.get(b.name.as_str()).copied();
+        }
+        let marked: HashSet<String> = marked.into_iter().map(str::to_string).collect();
+        self.branches = branches;
+        self.pin_to_top();
+        if self.group_by_prefix {
+            group_by_prefix(&mut self.branches);
+        }
+        self.selected_for_action = self
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| marked.contains(&b.name))
+            .map(|(i, _)| i)
+            .collect();
+        self.hidden_by_limit = hidden_by_limit;
+        self.detached_head = detached;
+        self.commit_cache.clear();
+        self.diff_stat_cache.clear();
+        self.stats_checked.clear();
+        self.set_date_format(self.date_format);
+        if !self.print_only {
+            self.is_dirty = is_working_tree_dirty().unwrap_or(self.is_dirty);
+        }
+        if let Ok(repo) = Repository::open_from_env() {
+            self.stash_count = stash_count(&repo);
+        }
+        if let Some(timeout) = self.gh_timeout {
+            // Refreshing is for seeing what's changed, so skip the cache
+            self.pr_fetch = Some(spawn_pr_fetch(timeout, self.repo_path.clone(), false));
+            self.pr_timed_out = false;
+        }
+        self.refilter(selected_name.as_deref());
+        self.status_message = Some(format!("Refreshed {} branches", self.branches.len()));
+    }
+
+    fn copy_name(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        self.status_message = Some(match copy_to_clipboard(&branch.name) {
+            Ok(()) => format!("Copied '{}'", branch.name),
+            Err(err) => err,
+        });
+    }
+
+    fn clear_search(&mut self) {
+        self.search_query.clear();
+        self.searching = self.type_to_filter;
+        self.update_filter();
+    }
+
+    /// Selects and checks out the branch that was checked out before this
+    /// one, like `git checkout -`.
+    fn checkout_previous(&mut self) {
+        let previous = git_output(&["rev-parse", "--symbolic-full-name", "@{-1}"])
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        let Some(name) = previous
+            .as_deref()
+            .and_then(|refname| refname.strip_prefix("refs/heads/"))
+        else {
+            self.status_message = Some("No previous branch to go back to".to_string());
+            return;
+        };
+        let Some(pos) = self
+            .filtered_indices
+            .iter()
+            .position(|&i| self.branches[i].name == name && !self.branches[i].is_tag())
+        else {
+            self.status_message = Some(format!("The previous branch '{}' isn't listed", name));
+            return;
+        };
+        self.state.select(Some(pos));
+        request_checkout(self);
+    }
+
+    /// Checks out the highlighted branch in a new worktree instead of here,
+    /// or if it already has one, just reports where that is. Quits on
+    /// success so the path can be printed.
+    fn open_worktree(&mut self) {
+        let Some(branch) = self.selected_branch() else {
+            return;
+        };
+        if let Some(path) = &branch.worktree {
+            self.opened_worktree = Some(path.clone());
+            self.checked_out = true;
+            self.quit();
+            return;
+        }
+        if branch.is_current {
+            self.status_message = Some(format!("'{}' is checked out here", branch.name));
+            return;
+        }
+        let repo_name = self
+            .repo_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let path = self.repo_path.join(
+            self.worktree_path_template
+                .replace("{branch}", &branch.name)
+                .replace("{repo}", &repo_name),
+        );
+        let path_arg = path.to_string_lossy().into_owned();
+        let args: Vec<String> = match &branch.remote_ref {
+            _ if branch.is_tag() => vec![
+                "--detach".to_string(),
+                path_arg,
+                format!("refs/tags/{}", branch.name),
+            ],
+            // Remote-only: create a local tracking branch there
+            Some(remote_ref) => vec![
+                "--track".to_string(),
+                "-b".to_string(),
+                branch.name.clone(),
+                path_arg,
+                remote_ref.clone(),
+            ],
+            None => vec![path_arg, branch.name.clone()],
+        };
+        let mut command = vec!["worktree", "add"];
+        command.extend(args.iter().map(String::as_str));
+        match self.git(&command) {
+            Ok(()) => {
+                // Tidies up the `..` from the template once it exists
+                self.opened_worktree = Some(path.canonicalize().unwrap_or(path));
+                self.checked_out = true;
+                self.quit();
+            }
+            Err(err) => {
+                self.status_message = Some(err.lines().last().unwrap_or_default().to_string());
+            }
+        }
+    }
+
+    /// Handles a change to `search_query` while typing it.
+    fn search_changed(&mut self) {
+        match self.search_mode {
+            SearchMode::Filter => self.update_filter(),
+            SearchMode::Jump => self.search_jump(),
+        }
+    }
+}
+
+impl DateFormat {
+    fn next(self) -> Self {
+        match self {
+            DateFormat::Relative => DateFormat::Iso,
+            DateFormat::Iso => DateFormat::Short,
+            DateFormat::Short => DateFormat::Relative,
+        }
+    }
+
+    fn format(self, timestamp: i64, offset_minutes: i32, now: i64) -> String {
+        match self {
+            DateFormat::Relative => format_relative_time(timestamp, now),
+            DateFormat::Iso => format_absolute_time(timestamp, offset_minutes, true),
+            DateFormat::Short => format_absolute_time(timestamp, offset_minutes, false),
+        }
+    }
+}
+
+impl SortMode {
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Date => "date",
+            SortMode::DateAsc => "date asc",
+            SortMode::Name => "name",
+            SortMode::Recent => "recent",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SortMode::Date => SortMode::DateAsc,
+            SortMode::DateAsc => SortMode::Name,
+            SortMode::Name => SortMode::Recent,
+            SortMode::Recent => SortMode::Date,
+        }
+    }
+
+    fn sort(self, branches: &mut [BranchInfo]) {
+        match self {
+            SortMode::Date => branches.sort_by_key(|b| std::cmp::Reverse(b.last_commit_timestamp)),
+            SortMode::DateAsc => branches.sort_by_key(|b| b.last_commit_timestamp),
+            SortMode::Name => branches.sort_by_cached_key(|b| b.name.to_lowercase()),
+            // Never checked out sorts last, newest commit first
+            SortMode::Recent => branches.sort_by_key(|b| {
+                std::cmp::Reverse((b.last_checkout_timestamp, b.last_commit_timestamp))
+            }),
+        }
+    }
+}
+
+/// Byte range of the first case-insensitive occurrence of `needle`.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack.char_indices().find_map(|(start, _)| {
+        let mut chars = haystack[start..].char_indices();
+        for n in needle.chars() {
+            let (_, c) = chars.next()?;
+            if !c.to_lowercase().eq(n.to_lowercase()) {
+                return None;
+            }
+        }
+        let end = chars.next().map_or(haystack.len(), |(i, _)| start + i);
+        Some(start..end)
+    })
+}
+
+/// Returns true if all characters of `query` appear in `name` in order
+/// (case-insensitive).
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name_chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| name_chars.any(|c| c == q))
+}
+
+/// Whether output to `stream` may be colored: it has to be a terminal, and
+/// `NO_COLOR` (https://no-color.org) mustn't be set.
+fn use_color(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Whether the list has to be drawn on stderr: with `--print`, or when
+/// stdout is redirected, stdout is kept for what gets printed on exit.
+fn tui_on_stderr(print_only: bool) -> bool {
+    print_only || !io::stdout().is_terminal()
+}
+
+fn main() -> Result<()> {
+    if use_color(&io::stderr()) {
+        color_eyre::install()?;
+    } else {
+        color_eyre::config::HookBuilder::default()
+            .theme(color_eyre::config::Theme::new())
+            .install()?;
+    }
+    let args = Args::parse_with_env();
+    // Every git, gh and glab command then runs there too
+    if let Some(dir) = &args.cwd {
+        std::env::set_current_dir(dir)?;
+    }
+    let keys = KeyConfig::load()?;
+    let mut theme = Theme::load()?;
+    let settings = Settings::load()?;
+
+    let inside_work_tree = match is_inside_work_tree() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("git-checkoutui: git is not installed or not on PATH");
+            std::process::exit(1);
+        }
+        result => result?,
+    };
+    if !inside_work_tree {
+        eprintln!("git-checkoutui: not a git repository (or any of the parent directories)");
+        std::process::exit(1);
+    }
+
+    if args.back {
+        let subcommand = if args.use_switch {
+            "switch"
+        } else {
+            "checkout"
+        };
+        let mut command = vec![subcommand];
+        if args.force {
+            command.push("-f");
+        }
+        command.push("-");
+        if args.dry_run {
+            println!("git {}", command.join(" "));
+            return Ok(());
+        }
+        // git reports its own errors, e.g. when there's no previous branch
+        let status = std::process::Command::new("git").args(command).status()?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    if args.fetch {
+        fetch_remotes()?;
+    }
+
+    let repo = Repository::open_from_env()?;
+    let repo_path = repo.workdir().unwrap_or(repo.path()).to_path_buf();
+    // gh can be slow, so query it in the background and show PRs when ready
+    // A fetch may have brought in new PR branches, so skip the cache then
+    let use_cache = !args.no_cache && !args.fetch;
+    let pr_fetch =
+        (!args.no_pr).then(|| spawn_pr_fetch(args.gh_timeout, repo_path.clone(), use_cache));
+    let list_options = ListOptions {
+        include_tags: args.tags,
+        default_branch: args
+            .default_branch
+            .clone()
+            .or(settings.default_branch.clone()),
+        with_stats: args.with_stats,
+        hide_current: args.hide_current,
+        since: args.since.clone(),
+        limit: args.limit,
+    };
+    if let Some(name) = &list_options.default_branch
+        && find_branch_ref(&repo, name).is_none()
+    {
+        eprintln!("git-checkoutui: default branch '{}' not found", name);
+        std::process::exit(1);
+    }
+    let (mut branches, hidden_by_limit) = load_branches(&repo, args.sort, &list_options)?;
+    // Only up front: a refresh leaves them to be checked as they're selected
+    if args.verify {
+        for branch in &mut branches {
+            branch.signature = Some(commit_signature(branch.rev()));
+        }
+    }
+
+    if args.json {
+        if let Some(fetch) = pr_fetch {
+            // get_pr_map gives up on its own after --gh-timeout
+            let pr_map = fetch.receiver.recv().ok().and_then(Result::ok);
+            let pr_map = pr_map.unwrap_or_default();
+            for b in branches.iter_mut().filter(|b| !b.is_tag()) {
+                b.pr = pr_map.get(&b.name).copied();
+            }
+        }
+        if args.date != DateFormat::Relative {
+            let now = unix_now();
+            for b in &mut branches {
+                b.last_commit_date =
+                    args.date
+                        .format(b.last_commit_timestamp, b.last_commit_offset, now);
+            }
+        }
+        println!("{}", serde_json::to_string_pretty(&branches)?);
+        return Ok(());
+    }
+    // Group headers take a row each
+    let header_rows = if args.group {
+        let prefixes: HashSet<_> = branches.iter().map(|b| branch_prefix(&b.name)).collect();
+        prefixes.len()
+    } else {
+        0
+    };
+    let max_height = args.max_height.unwrap_or(settings.max_height);
+    let height = if branches.is_empty() {
+        3
+    } else {
+        // 2 for borders, 1 for the footer
+        (branches.len() + header_rows)
+            .saturating_add(3)
+            .min(max_height as usize) as u16
+    };
+
+    // Nothing gets checked out with --print, so uncommitted changes don't matter
+    let is_dirty = !args.print && is_working_tree_dirty()?;
+
+    // The inline viewport asks for the cursor position on stdout, so on
+    // stderr the list is drawn fullscreen
+    let on_stderr = tui_on_stderr(args.print);
+    let fullscreen = args.fullscreen || on_stderr;
+    let tui_output = || -> Box<dyn io::Write> {
+        if on_stderr {
+            Box::new(io::stderr())
+        } else {
+            Box::new(stdout())
+        }
+    };
+    let viewport = if fullscreen {
+        Viewport::Fullscreen
+    } else {
+        Viewport::Inline(height)
+    };
+    // `viewport` is where the list was last drawn, if it got that far
+    let restore_terminal = |viewport: Option<Rect>| -> io::Result<()> {
+        disable_raw_mode()?;
+        tui_output().execute(DisableMouseCapture)?;
+        tui_output().execute(DisableBracketedPaste)?;
+        if fullscreen {
+            tui_output().execute(terminal::LeaveAlternateScreen)?;
+        } else if let Some(viewport) = viewport {
+            // Resizing can move the viewport, so go by where it ended up
+            // rather than where the cursor started
+            tui_output().execute(cursor::MoveTo(0, viewport.bottom()))?;
+        }
+        Ok(())
+    };
+
+    // Terminal initialization
+    enable_raw_mode()?;
+    tui_output().execute(EnableMouseCapture)?;
+    // A paste then arrives as one event rather than keystrokes, so a
+    // newline in it can't submit a prompt
+    tui_output().execute(EnableBracketedPaste)?;
+    if fullscreen {
+        tui_output().execute(terminal::EnterAlternateScreen)?;
+    }
+    let mut terminal = match Terminal::with_options(
+        CrosstermBackend::new(tui_output()),
+        TerminalOptions { viewport },
+    ) {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            restore_terminal(None)?;
+            return Err(e.into());
+        }
+    };
+
+    let colored = if on_stderr {
+        use_color(&io::stderr())
+    } else {
+        use_color(&io::stdout())
+    };
+    if !colored {
+        theme = theme.without_colors();
+    }
+    let mut app = App::new(branches, args.page_size, keys, theme, args.sort);
+    app.pinned_in_config = settings.pinned.clone();
+    app.pinned = state::load_pinned(&repo_path);
+    app.repo_path = repo_path.clone();
+    app.worktree_path_template = settings.worktree_path.clone();
+    app.checkout_template = args
+        .checkout_command
+        .as_ref()
+        .or(settings.checkout_command.as_ref())
+        .map(|command| command.split_whitespace().map(str::to_string).collect());
+    app.pin_to_top();
+    if args.group {
+        app.toggle_group_by_prefix();
+    }
+    app.pr_fetch = pr_fetch;
+    app.is_dirty = is_dirty;
+    app.stash_count = stash_count(&repo);
+    app.dry_run = args.dry_run;
+    app.print_only = args.print;
+    app.tui_on_stderr = on_stderr;
+    app.fullscreen = fullscreen;
+    app.use_switch = args.use_switch;
+    app.autostash = args.autostash;
+    app.force = args.force;
+    app.wrap_navigation = settings.wrap_navigation;
+    app.verbose = args.verbose;
+    app.compact = args.compact;
+    if args.icons || settings.icons {
+        app.glyphs = &NERD_FONT_GLYPHS;
+    }
+    app.line_numbers = args.line_numbers;
+    // Typing always filters, whatever `search` in config.toml says
+    app.search_mode = if args.type_to_filter {
+        SearchMode::Filter
+    } else {
+        settings.search
+    };
+    app.type_to_filter = args.type_to_filter;
+    app.regex_search = args.regex;
+    app.searching = args.type_to_filter;
+    app.hidden_by_limit = hidden_by_limit;
+    app.list_options = list_options;
+    app.gh_timeout = (!args.no_pr).then_some(args.gh_timeout);
+    app.detached_head = detached_head(&repo);
+    if args.date != DateFormat::Relative {
+        app.set_date_format(args.date);
+    }
+
+    // Prefer where the cursor was left last time, then the current branch
+    let last_selected = state::load_last_selected(&repo_path);
+    let initial_selection = last_selected
+        .and_then(|name| app.branches.iter().position(|b| b.name == name))
+        .or_else(|| app.branches.iter().position(|b| b.is_current))
+        .or(if app.branches.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+
+    if let Some(selected_index) = initial_selection {
+        app.state.select(Some(selected_index));
+    }
+
+    let result = run_app(&mut terminal, &mut app);
+    // Give the terminal back before reporting any error from the UI
+    restore_terminal(Some(terminal.get_frame().area()))?;
+    result?;
+
+    if let Some(branch) = app.selected_branch() {
+        // Not worth failing over; the next run just starts at the current branch
+        let _ = state::save_last_selected(&repo_path, &branch.name);
+    }
+
+    if args.print {
+        if let Some(branch) = app.last_checked_out_branch {
+            // The name's own bytes, so `git checkout "$(git checkoutui --print)"`
+            // works for names that aren't UTF-8
+            let mut stdout = stdout();
+            stdout.write_all(branch.as_encoded_bytes())?;
+            stdout.write_all(b"\n")?;
+        }
+    } else if app.dry_run {
+        for command in &app.dry_run_commands {
+            let quoted: Vec<_> = command.iter().map(|arg| shell_quote(arg)).collect();
+            println!("{}", quoted.join(" "));
+        }
+    } else {
+        // Pass on what git said, e.g. "Switched to branch ..."
+        for output in &app.checkout_output {
+            io::Write::write_all(&mut io::stdout(), &output.stdout)?;
+            io::Write::write_all(&mut io::stderr(), &output.stderr)?;
+        }
+        if app.stash_pop_failed {
+            eprintln!(
+                "git-checkoutui: `git stash pop` failed; your changes are still in the stash"
+            );
+        }
+        if app.keep_stash && app.checked_out {
+            eprintln!(
+                "git-checkoutui: your changes are in the stash; `git stash pop` restores them"
+            );
+        }
+        if let Some(path) = &app.opened_worktree {
+            println!("Worktree: {}", path.display());
+        }
+    }
+
+    if !app.checked_out {
+        io::stdout().flush()?;
+        std::process::exit(EXIT_CANCELLED);
+    }
+    if let Some(command) = &args.exec {
+        if args.dry_run {
+            println!("{}", command);
+        } else if !args.print {
+            // A new worktree is where the branch now is
+            let dir = app.opened_worktree.as_deref().unwrap_or(&repo_path);
+            let status = shell_command(command).current_dir(dir).status()?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `command` run by the platform's shell, as `--exec` expects.
+fn shell_command(command: &str) -> std::process::Command {
+    if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+/// Exit code when the list was closed without checking anything out, so
+/// scripts can tell that apart from a checkout.
+const EXIT_CANCELLED: i32 = 1;
+
+/// Runs `git fetch --all --prune`, warning instead of failing if it doesn't
+/// work (e.g. when offline) so the list still opens with what's known.
+fn fetch_remotes() -> io::Result<()> {
+    // The progress line is wiped with escape codes, which would only
+    // clutter a log
+    let progress = io::stderr().is_terminal();
+    if progress {
+        eprint!("Fetching remotes…");
+    }
+    let output = std::process::Command::new("git")
+        .args(["fetch", "--all", "--prune", "--quiet"])
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    if progress {
+        eprint!("\r");
+        io::stderr().execute(terminal::Clear(terminal::ClearType::CurrentLine))?;
+    }
+    if !output.status.success() {
+        eprintln!(
+            "git-checkoutui: warning: fetch failed, showing stale data: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Quotes `arg` for a POSIX shell, leaving it bare if that's unambiguous.
+fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:@%+=,".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")))
+    }
+}
+
+/// True if tracked files have uncommitted changes.
+fn is_working_tree_dirty() -> io::Result<bool> {
+    let output = std::process::Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()?;
+    Ok(output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+fn is_inside_work_tree() -> io::Result<bool> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stderr(std::process::Stdio::null())
+        .output()?;
+    Ok(output.status.success() && output.stdout.trim_ascii() == b"true")
+}
+
+/// Runs `command` to completion like `Command::output`, but kills it and
+/// returns a `TimedOut` error if it takes longer than `timeout`.
+fn output_with_timeout(
+    command: &mut std::process::Command,
+    timeout: Duration,
+) -> io::Result<std::process::Output> {
+    let mut child = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    // Drain stdout on another thread so a full pipe can't stall the child
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(&mut stdout, &mut buf).map(|_| buf)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = reader
+        .join()
+        .map_err(|_| io::Error::other("stdout reader panicked"))??;
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+/// How long fetched PRs are reused by later runs in the same repository.
+const PR_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Fetches PRs via `gh` (or MRs via `glab`), saving them for the next runs.
+/// Returns an empty map if neither is installed, or a `TimedOut` error if
+/// it doesn't answer within `timeout`.
+fn get_pr_map(timeout: Duration, repo_path: &Path) -> io::Result<HashMap<String, PrInfo>> {
+    let Some(provider) = PrProvider::detect() else {
+        return Ok(HashMap::new());
+    };
+
+    let pr_list_output = output_with_timeout(
+        std::process::Command::new(provider.cli()).args(provider.list_args()),
+        timeout,
+    )?;
+
+    if !pr_list_output.status.success() {
+        return Ok(HashMap::new()); // e.g. not a gh repository
+    }
+
+    let prs = provider.parse(&pr_list_output.stdout);
+    if let Ok(json) = serde_json::to_vec(&prs) {
+        // Not worth failing over; the next run just asks again
+        let _ = state::save_pr_cache(repo_path, &json);
+    }
+    Ok(prs)
+}
+
+/// Maps head branch names to their most relevant PR from `gh pr list
+/// --json` output. Unparseable output means no PR info.
+fn parse_pr_list(json: &[u8]) -> HashMap<String, PrInfo> {
+    let prs: Vec<PullRequest> = match serde_json::from_slice(json) {
+        Ok(prs) => prs,
+        Err(_) => return HashMap::new(),
+    };
+
+    let mut pr_map: HashMap<String, PrInfo> = HashMap::new();
+    for pr in prs {
+        let info = PrInfo {
+            provider: PrProvider::GitHub,
+            number: pr.number,
+            state: pr.state,
+            is_draft: pr.isDraft,
+        };
+        match pr_map.get(&pr.headRefName) {
+            Some(existing) if existing.priority() >= info.priority() => {}
+            _ => {
+                pr_map.insert(pr.headRefName, info);
+            }
+        }
+    }
+
+    pr_map
+}
+
+/// Maps source branch names to their most relevant MR from `glab mr list
+/// --output json` output, like `parse_pr_list`.
+fn parse_mr_list(json: &[u8]) -> HashMap<String, PrInfo> {
+    let mrs: Vec<MergeRequest> = match serde_json::from_slice(json) {
+        Ok(mrs) => mrs,
+        Err(_) => return HashMap::new(),
+    };
+
+    let mut mr_map: HashMap<String, PrInfo> = HashMap::new();
+    for mr in mrs {
+        let info = PrInfo {
+            provider: PrProvider::GitLab,
+            number: mr.iid,
+            state: match mr.state.as_str() {
+                "opened" => PrState::Open,
+                "merged" => PrState::Merged,
+                _ => PrState::Closed,
+            },
+            is_draft: mr.draft,
+        };
+        match mr_map.get(&mr.source_branch) {
+            Some(existing) if existing.priority() >= info.priority() => {}
+            _ => {
+                mr_map.insert(mr.source_branch, info);
+            }
+        }
+    }
+
+    mr_map
+}
+
+/// Runs `get_pr_map` on another thread, unless `use_cache` and a recent
+/// run left its output behind; `App::poll_prs` picks up the result.
+fn spawn_pr_fetch(timeout: Duration, repo_path: PathBuf, use_cache: bool) -> PrFetch {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = match use_cache
+            .then(|| state::load_pr_cache(&repo_path, PR_CACHE_TTL))
+            .flatten()
+        {
+            Some(json) => Ok(serde_json::from_slice(&json).unwrap_or_default()),
+            None => get_pr_map(timeout, &repo_path),
+        };
+        // The receiver is gone if the user already quit
+        let _ = sender.send(result);
+    });
+    PrFetch {
+        receiver,
+        started: Instant::now(),
+    }
+}
+
+fn get_branch_info(
+    repo: &Repository,
+    sort_mode: SortMode,
+    options: &ListOptions,
+) -> Result<Vec<BranchInfo>, git2::Error> {
+    let mut branches = collect_branches(repo)?;
+    if options.include_tags {
+        branches.extend(collect_tags(repo)?);
+    }
+    compare_to_default(
+        repo,
+        &mut branches,
+        options.default_branch.as_deref(),
+        options.with_stats,
+    );
+    let worktrees = get_worktree_branches(repo);
+    let descriptions = get_branch_descriptions(repo);
+    let checkouts = get_checkout_times(repo);
+    for branch in &mut branches {
+        branch.last_checkout_timestamp = checkouts.get(&branch.name).copied();
+        if branch.remote_ref.is_none() && !branch.is_tag() {
+            branch.worktree = worktrees.get(&branch.name).cloned();
+            branch.description = descriptions.get(&branch.name).cloned();
+        }
+    }
+    sort_mode.sort(&mut branches);
+    Ok(branches)
+}
+
+/// Reads the refs `options` asks for, in `sort_mode` order, along with how
+/// many `--limit` left out.
+fn load_branches(
+    repo: &Repository,
+    sort_mode: SortMode,
+    options: &ListOptions,
+) -> Result<(Vec<BranchInfo>, usize), git2::Error> {
+    let mut branches = get_branch_info(repo, sort_mode, options)?;
+    if options.hide_current {
+        // Selection then falls back to the top row
+        branches.retain(|b| !b.is_current);
+    }
+    if let Some(since) = &options.since {
+        let cutoff = unix_now() - since.seconds;
+        branches.retain(|b| b.last_commit_timestamp >= cutoff);
+    }
+    let hidden_by_limit = match options.limit {
+        Some(limit) if limit < branches.len() => {
+            // Keep the most recently committed, whatever the display order
+            SortMode::Date.sort(&mut branches);
+            let hidden = branches.len() - limit;
+            branches.truncate(limit);
+            sort_mode.sort(&mut branches);
+            hidden
+        }
+        _ => 0,
+    };
+    Ok((branches, hidden_by_limit))
+}
+
+/// Number of stashes, which git keeps as the reflog of `refs/stash`.
+fn stash_count(repo: &Repository) -> usize {
+    repo.reflog("refs/stash").map_or(0, |reflog| reflog.len())
+}
+
+/// The abbreviated commit HEAD points at, if it's detached rather than on
+/// a branch.
+fn detached_head(repo: &Repository) -> Option<String> {
+    if !repo.head_detached().unwrap_or(false) {
+        return None;
+    }
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let short_id = commit.as_object().short_id().ok()?;
+    short_id.as_str().ok().map(str::to_string)
+}
+
+/// The ref of a branch given as `name`, `origin/name` or `remote/name`,
+/// preferring a local branch.
+fn find_branch_ref(repo: &Repository, name: &str) -> Option<String> {
+    [
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/origin/{}", name),
+    ]
+    .into_iter()
+    .find(|refname| repo.find_reference(refname).is_ok())
+}
+
+/// The default branch's ref: `explicit` (`--default-branch`) if given and
+/// found, else what `origin/HEAD` points at, or else a local `main` or
+/// `master`.
+fn default_branch_ref(repo: &Repository, explicit: Option<&str>) -> Option<String> {
+    if let Some(refname) = explicit.and_then(|name| find_branch_ref(repo, name)) {
+        return Some(refname);
+    }
+    if let Ok(head) = repo.find_reference("refs/remotes/origin/HEAD")
+        && let Ok(Some(target)) = head.symbolic_target()
+    {
+        return Some(target.to_string());
+    }
+    ["refs/heads/main", "refs/heads/master"]
+        .into_iter()
+        .find(|name| repo.find_reference(name).is_ok())
+        .map(str::to_string)
+}
+
+/// Sets `is_merged` on branches whose tips the default branch contains, like
+/// `git branch --merged <default>`, and with `count_ahead` also
+/// `commits_ahead_of_main`. The default branch itself is left alone.
+fn compare_to_default(
+    repo: &Repository,
+    branches: &mut [BranchInfo],
+    default_branch: Option<&str>,
+    count_ahead: bool,
+) {
+    let Some(default_ref) = default_branch_ref(repo, default_branch) else {
+        return;
+    };
+    let Ok(default_oid) = repo.refname_to_id(&default_ref) else {
+        return;
+    };
+    let default_name = default_ref.strip_prefix("refs/heads/").or_else(|| {
+        let remote_branch = default_ref.strip_prefix("refs/remotes/")?;
+        remote_branch.split_once('/').map(|(_, name)| name)
+    });
+    for branch in branches.iter_mut().filter(|b| !b.is_tag()) {
+        if Some(branch.name.as_str()) == default_name {
+            continue;
+        }
+        let refname = match &branch.remote_ref {
+            Some(remote_ref) => format!("refs/remotes/{}", remote_ref),
+            None => format!("refs/heads/{}", branch.name),
+        };
+        let Ok(oid) = repo.refname_to_id(&refname) else {
+            continue;
+        };
+        branch.is_merged =
+            oid == default_oid || repo.graph_descendant_of(default_oid, oid).unwrap_or(false);
+        if count_ahead {
+            // Like `git rev-list --count <default>..<branch>`
+            branch.commits_ahead_of_main = repo
+                .graph_ahead_behind(oid, default_oid)
+                .ok()
+                .map(|(ahead, _)| ahead as u32);
+        }
+    }
+}
+
+/// Maps branch names to when they were last checked out, from the
+/// "checkout: moving from <old> to <new>" entries in HEAD's reflog (`git
+/// switch` writes them too). Errors just mean no checkout times.
+fn get_checkout_times(repo: &Repository) -> HashMap<String, i64> {
+    let mut checkouts = HashMap::new();
+    let Ok(reflog) = repo.reflog("HEAD") else {
+        return checkouts;
+    };
+    // Newest entries come first, so the first one per branch is the latest
+    for entry in reflog.iter() {
+        let Some(moved) = entry
+            .message()
+            .ok()
+            .flatten()
+            .and_then(|message| message.strip_prefix("checkout: moving from "))
+        else {
+            continue;
+        };
+        if let Some((_, to)) = moved.rsplit_once(" to ") {
+            checkouts
+                .entry(to.to_string())
+                .or_insert_with(|| entry.committer().when().seconds());
+        }
+    }
+    checkouts
+}
+
+/// Maps branch names to their descriptions, like `git config --get-regexp
+/// 'branch\..*\.description'`. Errors just mean no descriptions.
+fn get_branch_descriptions(repo: &Repository) -> HashMap<String, String> {
+    let mut descriptions = HashMap::new();
+    let Ok(config) = repo.config().and_then(|mut config| config.snapshot()) else {
+        return descriptions;
+    };
+    let Ok(mut entries) = config.entries(Some(r"^branch\..*\.description$")) else {
+        return descriptions;
+    };
+    while let Some(Ok(entry)) = entries.next() {
+        let (Ok(key), Ok(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        // Branch names may contain dots, so only the ends are fixed
+        let Some(name) = key
+            .strip_prefix("branch.")
+            .and_then(|key| key.strip_suffix(".description"))
+        else {
+            continue;
+        };
+        let value = value.trim();
+        if !value.is_empty() {
+            descriptions.insert(name.to_string(), value.to_string());
+        }
+    }
+    descriptions
+}
+
+/// Maps branch names to the other worktrees they're checked out in, from
+/// `git worktree list --porcelain`. Errors just mean no worktree info.
+fn get_worktree_branches(repo: &Repository) -> HashMap<String, PathBuf> {
+    let Ok(output) = std::process::Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .stderr(std::process::Stdio::null())
+        .output()
+    else {
+        return HashMap::new();
+    };
+    if !output.status.success() {
+        return HashMap::new();
+    }
+    let this_worktree = repo.workdir().and_then(|dir| dir.canonicalize().ok());
+
+    let mut worktrees = HashMap::new();
+    // Entries are blocks of `key value` lines separated by blank lines
+    for entry in String::from_utf8_lossy(&output.stdout).split("\n\n") {
+        let mut path = None;
+        let mut branch = None;
+        for line in entry.lines() {
+            if let Some(value) = line.strip_prefix("worktree ") {
+                path = Some(PathBuf::from(value));
+            } else if let Some(value) = line.strip_prefix("branch refs/heads/") {
+                branch = Some(value.to_string());
+            }
+        }
+        if let (Some(path), Some(branch)) = (path, branch)
+            && path.canonicalize().ok() != this_worktree
+        {
+            worktrees.insert(branch, path);
+        }
+    }
+    worktrees
+}
+
+/// Reads local branches, plus remote branches that have no local
+/// counterpart, straight from the repository; branch names are never
+/// parsed out of command output, so any valid ref name is listed.
+fn collect_branches(repo: &Repository) -> Result<Vec<BranchInfo>, git2::Error> {
+    let now = unix_now();
+
+    // 1. Get local branches and populate the map
+    let mut branches_map: HashMap<String, BranchInfo> = HashMap::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Some(info) = local_branch_info(repo, &branch, now)? {
+            branches_map.insert(info.name.clone(), info);
+        }
+    }
+
+    // 2. Get remote branches and merge/update info
+    for branch in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = branch?;
+        let remote_ref_bytes = branch.name_bytes()?;
+        let remote_ref_name = String::from_utf8_lossy(remote_ref_bytes).into_owned();
+        if remote_ref_name.ends_with("/HEAD") {
+            continue;
+        }
+        let Ok(commit) = branch.get().peel_to_commit() else {
+            continue;
+        };
+
+        let short_name = match remote_ref_name.split_once('/') {
+            Some((_, branch)) => branch,
+            None => &remote_ref_name,
+        };
+        let raw_short_name = match remote_ref_bytes.iter().position(|&b| b == b'/') {
+            Some(slash) => &remote_ref_bytes[slash + 1..],
+            None => remote_ref_bytes,
+        };
+
+        let remote_when = commit.committer().when();
+        let remote_timestamp = remote_when.seconds();
+        let remote_last_commit_date = format_relative_time(remote_timestamp, now);
+        let remote_author = String::from_utf8_lossy(commit.author().name_bytes()).into_owned();
+        let remote_subject = commit_subject(&commit);
+
+        if let Some(existing_branch) = branches_map.get_mut(short_name) {
+            // Branch exists locally, update if remote is newer
+            if remote_timestamp > existing_branch.last_commit_timestamp {
+                existing_branch.last_commit_timestamp = remote_timestamp;
+                existing_branch.last_commit_offset = remote_when.offset_minutes();
+                existing_branch.last_commit_date = remote_last_commit_date;
+                existing_branch.last_author = remote_author;
+                existing_branch.last_subject = remote_subject;
+            }
+        } else {
+            // Branch is remote-only, add it.
+            let info = BranchInfo {
+                name: short_name.to_string(),
+                raw_name: os_string(raw_short_name),
+                ref_type: RefType::Branch,
+                tracking_info: "remote".to_string(),
+                last_commit_date: remote_last_commit_date,
+                last_commit_timestamp: remote_timestamp,
+                last_commit_offset: remote_when.offset_minutes(),
+                last_author: remote_author,
+                last_subject: remote_subject,
+                is_merged: false,
+                commits_ahead_of_main: None,
+                has_upstream: true, // It is an upstream branch
+                upstream: None,
+                pr: None,
+                is_current: false,
+                remote_ref: Some(remote_ref_name.clone()),
+                worktree: None,
+                description: None,
+                last_checkout_timestamp: None,
+                signature: None,
+            };
+            branches_map.insert(short_name.to_string(), info);
+        }
+    }
+
+    Ok(branches_map.into_values().collect())
+}
+
+/// Reads a local branch and how it compares to its upstream, or `None` if
+/// it doesn't point at a commit.
+fn local_branch_info(
+    repo: &Repository,
+    branch: &git2::Branch,
+    now: i64,
+) -> Result<Option<BranchInfo>, git2::Error> {
+    let name_bytes = branch.name_bytes()?;
+    let Ok(commit) = branch.get().peel_to_commit() else {
+        return Ok(None);
+    };
+    let when = commit.committer().when();
+    let timestamp = when.seconds();
+
+    // An upstream is configured even if its ref has since been deleted.
+    // libgit2 only looks it up by a UTF-8 refname.
+    let upstream_name = branch
+        .get()
+        .name()
+        .ok()
+        .and_then(|refname| repo.branch_upstream_name(refname).ok())
+        .and_then(|name| name.as_str().ok().map(str::to_string));
+    let has_upstream = upstream_name.is_some();
+    let upstream = upstream_name.as_deref().map(|name| {
+        name.strip_prefix("refs/remotes/")
+            .or_else(|| name.strip_prefix("refs/heads/"))
+            .unwrap_or(name)
+            .to_string()
+    });
+    let tracking_info = match upstream_name {
+        Some(upstream_name) => match repo.refname_to_id(&upstream_name) {
+            Ok(upstream_oid) => {
+                let (ahead, behind) = repo.graph_ahead_behind(commit.id(), upstream_oid)?;
+                format_tracking(ahead, behind)
+            }
+            Err(_) => "gone".to_string(),
+        },
+        None => String::new(),
+    };
+
+    Ok(Some(BranchInfo {
+        name: String::from_utf8_lossy(name_bytes).into_owned(),
+        raw_name: os_string(name_bytes),
+        ref_type: RefType::Branch,
+        tracking_info,
+        last_commit_date: format_relative_time(timestamp, now),
+        last_commit_timestamp: timestamp,
+        last_commit_offset: when.offset_minutes(),
+        last_author: String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
+        last_subject: commit_subject(&commit),
+        is_merged: false,
+        commits_ahead_of_main: None,
+        has_upstream,
+        upstream,
+        pr: None,
+        is_current: branch.is_head(),
+        remote_ref: None,
+        worktree: None,
+        description: None,
+        last_checkout_timestamp: None,
+        signature: None,
+    }))
+}
+
+/// Reads tags that point (possibly through annotated tag objects) at commits.
+fn collect_tags(repo: &Repository) -> Result<Vec<BranchInfo>, git2::Error> {
+    let now = unix_now();
+    let mut tags = Vec::new();
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        let name_bytes = reference.shorthand_bytes();
+        let when = commit.committer().when();
+        tags.push(BranchInfo {
+            name: String::from_utf8_lossy(name_bytes).into_owned(),
+            raw_name: os_string(name_bytes),
+            ref_type: RefType::Tag,
+            tracking_info: "tag".to_string(),
+            last_commit_date: format_relative_time(when.seconds(), now),
+            last_commit_timestamp: when.seconds(),
+            last_commit_offset: when.offset_minutes(),
+            last_author: String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
+            last_subject: commit_subject(&commit),
+            is_merged: false,
+            commits_ahead_of_main: None,
+            has_upstream: false,
+            upstream: None,
+            pr: None,
+            is_current: false,
+            remote_ref: None,
+            worktree: None,
+            description: None,
+            last_checkout_timestamp: None,
+            signature: None,
+        });
+    }
+    Ok(tags)
+}
+
+/// A ref name as git stores it, to hand back to git unchanged. Only Unix
+/// can carry bytes that aren't UTF-8 in an `OsString`.
+fn os_string(bytes: &[u8]) -> OsString {
+    #[cfg(unix)]
+    {
+        std::os::unix::ffi::OsStringExt::from_vec(bytes.to_vec())
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8_lossy(bytes).into_owned().into()
+    }
+}
+
+/// `--checkout-command`'s words with `{branch}` replaced by `branch`, or
+/// `branch` appended if none has it. Each word stays one argument, so
+/// nothing in the name is interpreted by a shell.
+fn expand_checkout_command(template: &[String], branch: &OsStr) -> Vec<OsString> {
+    let mut command: Vec<OsString> = template
+        .iter()
+        .map(|word| {
+            let mut parts = word.split("{branch}");
+            let mut arg = OsString::from(parts.next().unwrap_or_default());
+            for part in parts {
+                arg.push(branch);
+                arg.push(part);
+            }
+            arg
+        })
+        .collect();
+    if !template.iter().any(|word| word.contains("{branch}")) {
+        command.push(branch.to_owned());
+    }
+    command
+}
+
+/// `prefix` followed by `name`, e.g. `refs/tags/` and a tag.
+fn join_os(prefix: &str, name: &OsStr) -> OsString {
+    let mut joined = OsString::from(prefix);
+    joined.push(name);
+    joined
+}
+
+/// Checks the signature of `rev`'s commit with `git log --format=%G?`,
+/// which runs gpg (or ssh-keygen). Errors count as unsigned.
+fn commit_signature(rev: &str) -> SignatureStatus {
+    let output = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%G?", rev, "--"])
+        .stderr(std::process::Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            SignatureStatus::from_code(String::from_utf8_lossy(&output.stdout).trim())
+        }
+        _ => SignatureStatus::Unsigned,
+    }
+}
+
+/// The first line of `commit`'s message, squeezed onto one line.
+fn commit_subject(commit: &git2::Commit) -> String {
+    let subject = commit
+        .summary_bytes()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    subject.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Formats ahead/behind counts like `%(upstream:track,nobracket)`.
+fn format_tracking(ahead: usize, behind: usize) -> String {
+    match (ahead, behind) {
+        (0, 0) => String::new(),
+        (ahead, 0) => format!("ahead {}", ahead),
+        (0, behind) => format!("behind {}", behind),
+        (ahead, behind) => format!("ahead {}, behind {}", ahead, behind),
+    }
+}
+
+/// Formats `timestamp` in the committer's time zone like git's `iso` date
+/// format ("2024-05-01 13:45:00 +0900"), or just the date like `short`.
+fn format_absolute_time(timestamp: i64, offset_minutes: i32, with_time: bool) -> String {
+    let local = timestamp + i64::from(offset_minutes) * 60;
+    let (days, seconds) = (local.div_euclid(86400), local.rem_euclid(86400));
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    if !with_time {
+        return date;
+    }
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let offset = offset_minutes.unsigned_abs();
+    format!(
+        "{} {:02}:{:02}:{:02} {}{:02}{:02}",
+        date,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        sign,
+        offset / 60,
+        offset % 60
+    )
+}
+
+/// Formats `timestamp` relative to `now` the way git's `relative` date
+/// format does, e.g. "3 days ago" or "1 year, 2 months ago".
+fn format_relative_time(timestamp: i64, now: i64) -> String {
+    fn plural(n: i64, unit: &str) -> String {
+        if n == 1 {
+            format!("{} {}", n, unit)
+        } else {
+            format!("{} {}s", n, unit)
+        }
+    }
+
+    let seconds = now - timestamp;
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months > 0 {
+            return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+        }
+        return format!("{} ago", plural(years, "year"));
+    }
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+/// The files `branch` changes since it forked from `base`, like `git diff
+/// --stat base...branch`. Empty if there are none or git fails.
+fn get_diff_stat(base: &str, branch: &str, width: u16) -> Vec<String> {
+    let output = std::process::Command::new("git")
+        .arg("diff")
+        .arg(format!("--stat={}", width))
+        .arg(format!("{}...{}", base, branch))
+        .arg("--")
+        .stderr(std::process::Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn get_recent_commits(branch: &str) -> Vec<String> {
+    let output = std::process::Command::new("git")
+        .args(["log", "--oneline", "-n", "10", branch, "--"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Runs `gh pr view --web` (or `glab mr view --web`), capturing its output
+/// so it doesn't draw over the TUI.
+fn open_pr_in_browser(pr: PrInfo) -> Result<(), String> {
+    let output = std::process::Command::new(pr.provider.cli())
+        .args(pr.provider.view_args(pr.number))
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Pipes `text` into the first clipboard tool that works; e.g. `wl-copy` is
+/// installed but fails outside Wayland, so failures move on to the next.
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    const TOOLS: [(&str, &[&str]); 5] = [
+        ("pbcopy", &[]),
+        ("wl-copy", &[]),
+        ("xclip", &["-selection", "clipboard"]),
+        ("xsel", &["--clipboard", "--input"]),
+        ("clip.exe", &[]),
+    ];
+    for (program, args) in TOOLS {
+        let child = std::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            // A write error shows up as a failed exit status below
+            let _ = io::Write::write_all(&mut stdin, text.as_bytes());
+        }
+        if child.wait().is_ok_and(|status| status.success()) {
+            return Ok(());
+        }
+    }
+    Err("No working clipboard tool found".to_string())
+}
+
+/// Runs `git <args>`, capturing its output.
+fn git_output<S: AsRef<OsStr>>(args: &[S]) -> io::Result<std::process::Output> {
+    std::process::Command::new("git")
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+}
+
+/// Reads a single git config value, or `None` if it isn't set.
+fn git_config(key: &str) -> Option<String> {
+    let output = git_output(&["config", "--get", key]).ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// git's stderr from a failed command, ready to draw.
+fn error_text(output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Tabs would be drawn as a single cell
+    stderr.trim_end().replace('\t', "    ")
+}
+
+/// Runs `git <args>` quietly, returning git's stderr on failure.
+fn run_git(args: &[&str]) -> Result<(), String> {
+    let output = git_output(args).map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<Box<dyn io::Write>>>,
+    app: &mut App,
+) -> io::Result<()> {
+    while !app.should_quit {
+        terminal.draw(|f| ui(f, app))?;
+        // `ui` only draws what's been loaded, so git runs here instead and
+        // the result is drawn straight away
+        let stats_loaded = app.load_selected_stats();
+        if app.load_selected_commits() || stats_loaded {
+            continue;
+        }
+        if let Some(index) = app.pending_push.take() {
+            app.push_branch(index);
+            continue;
+        }
+        if let Some(index) = app.pending_pull.take() {
+            app.pull_branch(index);
+            continue;
+        }
+        if let Some(rev) = app.pending_log.take() {
+            if !show_log(terminal, app, &rev)? {
+                app.status_message = Some(format!("git log {} failed", rev));
+            }
+            continue;
+        }
+        app.load_selected_signature();
+        app.load_selected_diff_stat();
+        // Events are handled as soon as they arrive; otherwise the loop
+        // comes round every tick to pick up background work
+        let tick = if app.pr_fetch.is_some()
+            || app.signature_check.is_some()
+            || app.diff_stat_load.is_some()
+        {
+            SPINNER_TICK
+        } else {
+            TICK
+        };
+        if event::poll(tick)? {
+            handle_event(app, event::read()?);
+        }
+        app.refresh_stale_dates();
+        app.poll_prs();
+        app.poll_signature();
+        app.poll_diff_stat();
+    }
+    Ok(())
+}
+
+/// Hands the terminal to `git log <rev>` and its pager, then takes it back
+/// and redraws. Returns whether git succeeded.
+fn show_log(
+    terminal: &mut Terminal<CrosstermBackend<Box<dyn io::Write>>>,
+    app: &App,
+    rev: &str,
+) -> io::Result<bool> {
+    disable_raw_mode()?;
+    terminal.backend_mut().execute(DisableMouseCapture)?;
+    terminal.backend_mut().execute(DisableBracketedPaste)?;
+    // The pager then can't scroll the inline list away
+    if !app.fullscreen {
+        terminal
+            .backend_mut()
+            .execute(terminal::EnterAlternateScreen)?;
+    }
+
+    let mut command = std::process::Command::new("git");
+    command.arg("log");
+    let colored = if app.tui_on_stderr {
+        use_color(&io::stderr())
+    } else {
+        use_color(&io::stdout())
+    };
+    if !colored {
+        command.arg("--no-color");
+    }
+    command.args([rev, "--"]);
+    // git's default of `LESS=FRX` quits straight away when the log fits on
+    // screen, before it could be read
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "R");
+    }
+    if app.tui_on_stderr {
+        // stdout is kept for what gets printed on exit
+        command.stdout(io::stderr());
+    }
+    let status = command.status();
+
+    if !app.fullscreen {
+        terminal
+            .backend_mut()
+            .execute(terminal::LeaveAlternateScreen)?;
+    }
+    enable_raw_mode()?;
+    terminal.backend_mut().execute(EnableMouseCapture)?;
+    terminal.backend_mut().execute(EnableBracketedPaste)?;
+    terminal.clear()?;
+    Ok(status.is_ok_and(|status| status.success()))
+}
+
+/// How often relative dates are re-formatted while the list is open.
+const DATE_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for input before redrawing anyway, so background work
+/// (PRs arriving, dates ageing) shows without a keypress.
+const TICK: Duration = Duration::from_millis(250);
+
+/// A faster tick while PRs are loading, to animate the spinner.
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+
+fn handle_event(app: &mut App, event: Event) {
+    match event {
+        Event::Key(key) => handle_key(app, key),
+        Event::Mouse(mouse) => handle_mouse(app, mouse),
+        Event::Paste(text) => handle_paste(app, &text),
+        // The next draw picks up the new size; until then rows have moved
+        // under the mouse, so a click can't pair up with the last one
+        Event::Resize(..) => app.last_click = None,
+        _ => {}
+    }
+}
+
+/// Types pasted text into the open prompt or search, minus line breaks
+/// and other control characters. Outside of those it's ignored.
+fn handle_paste(app: &mut App, text: &str) {
+    let text = text.chars().filter(|c| !c.is_control());
+    if let Some(input) = &mut app.input {
+        input.buffer.extend(text);
+    } else if app.searching {
+        app.search_query.extend(text);
+        app.search_changed();
+    }
+}
+
+fn handle_key(app: &mut App, key: KeyEvent) {
+    if key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL {
+        app.quit();
+        return;
+    }
+    if app.show_help {
+        app.show_help = false;
+        return;
+    }
+    if app.pending_delete.is_some() {
+        match key.code {
+            KeyCode::Char('y') if app.delete_error.is_none() => app.confirm_delete(false),
+            KeyCode::Char('D') if app.delete_error.is_some() => app.confirm_delete(true),
+            _ => app.cancel_delete(),
+        }
+        return;
+    }
+    if let Some(checkout) = app.pending_dirty_checkout.take() {
+        match key.code {
+            KeyCode::Char('1' | 's') => {
+                app.keep_stash = true;
+                app.resume_dirty_checkout(checkout);
+            }
+            KeyCode::Char('2' | 'c') => app.resume_dirty_checkout(checkout),
+            _ => {}
+        }
+        return;
+    }
+    if app.pending_track_checkout {
+        app.pending_track_checkout = false;
+        if key.code == KeyCode::Char('y') {
+            checkout_unless_dirty(app);
+        }
+        return;
+    }
+    if app.pending_batch_delete {
+        match key.code {
+            KeyCode::Char('y') => app.confirm_batch_delete(),
+            _ => app.pending_batch_delete = false,
+        }
+        return;
+    }
+    app.status_message = None;
+    app.git_error = None;
+    if let Some(input) = &mut app.input {
+        match key.code {
+            KeyCode::Esc => app.input = None,
+            KeyCode::Backspace => {
+                input.buffer.pop();
+            }
+            KeyCode::Enter => app.submit_input(),
+            KeyCode::Char(c) => input.buffer.push(c),
+            _ => {}
+        }
+        return;
+    }
+    if app.searching {
+        match key.code {
+            // With nothing left to clear, Esc quits like it does in fzf
+            KeyCode::Esc if app.type_to_filter && app.search_query.is_empty() => app.quit(),
+            KeyCode::Esc => app.clear_search(),
+            KeyCode::Backspace => {
+                app.search_query.pop();
+                app.search_changed();
+            }
+            KeyCode::Down => app.next(),
+            KeyCode::Up => app.previous(),
+            KeyCode::PageDown => app.next_page(),
+            KeyCode::PageUp => app.prev_page(),
+            KeyCode::Home => app.first(),
+            KeyCode::End => app.last(),
+            // Leave the cursor on the match, so n/N can move on from there
+            KeyCode::Enter if app.search_mode == SearchMode::Jump => app.searching = false,
+            KeyCode::Enter => {
+                request_checkout(app);
+            }
+            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.toggle_regex_search()
+            }
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.half_page_down()
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.half_page_up()
+            }
+            // F5 and Ctrl-r still reload, but a refresh bound to a plain
+            // letter gets typed like any other
+            _ if matches!(app.keys.action(&key), Some(Action::Refresh))
+                && (!matches!(key.code, KeyCode::Char(_))
+                    || key.modifiers.contains(KeyModifiers::CONTROL)) =>
+            {
+                app.refresh()
+            }
+            KeyCode::Char(c) => {
+                app.search_query.push(c);
+                app.search_changed();
+            }
+            _ => {}
+        }
+        return;
+    }
+    if app.line_numbers {
+        match key.code {
+            KeyCode::Char(c)
+                if c.is_ascii_digit() && !key.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                app.number_input.push(c);
+                return;
+            }
+            KeyCode::Enter if !app.number_input.is_empty() => {
+                app.go_to_number();
+                return;
+            }
+            KeyCode::Esc if !app.number_input.is_empty() => {
+                app.number_input.clear();
+                return;
+            }
+            // Any other key gives up on the number and does its usual thing
+            _ => app.number_input.clear(),
+        }
+    }
+    match app.keys.action(&key) {
+        Some(Action::Quit) => app.quit(),
+        Some(Action::Next) => app.next(),
+        Some(Action::Previous) => app.previous(),
+        Some(Action::Checkout) => {
+            if !request_checkout(app) {
+                app.quit();
+            }
+        }
+        Some(Action::Delete) => app.request_delete(),
+        Some(Action::Refresh) => app.refresh(),
+        None => handle_normal_key(app, key),
+    }
+}
+
+/// Two clicks on the same row within this interval count as a double-click.
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+
+fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    if app.is_prompting() {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollDown => app.next(),
+        MouseEventKind::ScrollUp => app.previous(),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let area = app.list_area;
+            // Skip the top and bottom borders
+            let inner_top = area.y + 1;
+            let inner_bottom = (area.y + area.height).saturating_sub(1);
+            if mouse.row < inner_top
+                || mouse.row >= inner_bottom
+                || mouse.column < area.x
+                || mouse.column >= area.x + area.width
+            {
+                return;
+            }
+            let row = app.state.offset() + (mouse.row - inner_top) as usize;
+            // Clicking a group header does nothing
+            let Some(&ListRow::Branch(row)) = app.list_rows().get(row) else {
+                return;
+            };
+
+            let now = Instant::now();
+            let is_double_click = app.last_click.is_some_and(|(time, last_row)| {
+                last_row == row && now.duration_since(time) < DOUBLE_CLICK_INTERVAL
+            });
+            app.state.select(Some(row));
+            if is_double_click {
+                app.last_click = None;
+                request_checkout(app);
+            } else {
+                app.last_click = Some((now, row));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handles keys in normal mode that aren't configurable in `keys.toml`.
+fn handle_normal_key(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('d') if ctrl => app.half_page_down(),
+        KeyCode::Char('u') if ctrl => app.half_page_up(),
+        KeyCode::Esc if !app.search_query.is_empty() => app.clear_search(),
+        KeyCode::Char('/') => app.searching = true,
+        // Only while a jump search is active; Esc gives `n` back
+        KeyCode::Char('n') if !app.search_matches.is_empty() => app.cycle_match(false),
+        KeyCode::Char('N') if !app.search_matches.is_empty() => app.cycle_match(true),
+        KeyCode::Char('n' | 'c' | 'w') if app.print_only => {
+            app.status_message =
+                Some("Only existing branches can be picked with --print".to_string());
+        }
+        KeyCode::Char('n') => app.open_input(InputKind::NewBranch),
+        KeyCode::Char('R') => app.open_rename(),
+        KeyCode::Char('e') => app.open_describe(),
+        KeyCode::Char('v') => app.show_diff_stat = !app.show_diff_stat,
+        KeyCode::Char('c') => app.open_input(InputKind::Detach),
+        KeyCode::Char('s') => app.cycle_sort(),
+        KeyCode::Char('T') => app.cycle_date_format(),
+        KeyCode::Char('g') => app.toggle_hide_gone(),
+        KeyCode::Char('r') => app.toggle_hide_remotes(),
+        KeyCode::Char('p') => app.cycle_pr_filter(),
+        KeyCode::Char('t') => app.toggle_group_by_prefix(),
+        KeyCode::Char('o') => app.open_pr(),
+        KeyCode::Char('y') => app.copy_name(),
+        KeyCode::Char('f') => app.toggle_pin(),
+        KeyCode::Char('P') => app.request_pull(),
+        KeyCode::Char('u') => app.request_push(),
+        KeyCode::Char('l' | 'L') => {
+            app.pending_log = app.selected_branch().map(|b| b.rev().to_string())
+        }
+        KeyCode::PageDown => app.next_page(),
+        KeyCode::PageUp => app.prev_page(),
+        KeyCode::Home => app.first(),
+        KeyCode::End | KeyCode::Char('G') => app.last(),
+        KeyCode::Char('.') => app.select_current(),
+        KeyCode::Char('-') => app.checkout_previous(),
+        KeyCode::Char('w') => app.open_worktree(),
+        KeyCode::Char('?') => app.show_help = true,
+        KeyCode::Char(' ') => app.toggle_mark(),
+        KeyCode::Char('D') => app.request_batch_delete(),
+        _ => {}
+    }
+}
+
+/// Checks out the highlighted branch, first asking for confirmation
+/// before creating a tracking branch for a remote-only one, and if the
+/// working tree is dirty (unless `--force`). Returns false if nothing is
+/// selected.
+fn request_checkout(app: &mut App) -> bool {
+    match app.selected_branch() {
+        Some(BranchInfo {
+            name,
+            worktree: Some(path),
+            ..
+        }) => {
+            app.status_message = Some(format!(
+                "'{}' is checked out in worktree {}",
+                name,
+                path.display()
+            ));
+            true
+        }
+        // --print only hands the name over, so nothing gets created, and
+        // --force skips the question
+        Some(branch) if branch.remote_ref.is_some() && !app.print_only && !app.force => {
+            app.pending_track_checkout = true;
+            true
+        }
+        Some(_) => {
+            checkout_unless_dirty(app);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Checks out the highlighted branch, or asks first if the working tree is
+/// dirty.
+fn checkout_unless_dirty(app: &mut App) {
+    match app.selected_branch() {
+        Some(branch) if app.is_dirty && !app.autostash && !app.force && !branch.is_current => {
+            app.pending_dirty_checkout = Some(DirtyCheckout::Selected);
+        }
+        Some(_) => app.checkout_selected(),
+        None => {}
+    }
+}
+
+/// Author names are truncated to at most this many columns.
+const AUTHOR_MAX_WIDTH: usize = 16;
+
+/// Rows go compact rather than leave branch names fewer columns than this.
+const NAME_MIN_WIDTH: usize = 12;
+
+/// Subjects are left out rather than cut to fewer columns than this.
+const SUBJECT_MIN_WIDTH: usize = 8;
+
+/// A row's (possibly truncated) branch name, with the search match
+/// highlighted.
+fn name_spans(
+    name: String,
+    name_style: Style,
+    search_match: impl Fn(&str) -> Option<Range<usize>>,
+    match_color: Color,
+) -> Vec<Span<'static>> {
+    match search_match(&name) {
+        Some(range) => vec![
+            Span::styled(name[..range.start].to_string(), name_style),
+            Span::styled(
+                name[range.clone()].to_string(),
+                name_style
+                    .fg(match_color)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            ),
+            Span::styled(name[range.end..].to_string(), name_style),
+        ],
+        None => vec![Span::styled(name, name_style)],
+    }
+}
+
+/// Tints the whole row so HEAD stands out even when not selected; the
+/// reversed selection highlight still wins over it.
+fn current_row_style(theme: &Theme, b: &BranchInfo) -> Style {
+    if b.is_current {
+        Style::default().bg(theme.current_row)
+    } else {
+        Style::default()
+    }
+}
+
+/// The markers drawn in branch rows. Prefixes take up two columns, so the
+/// glyphs for them are one column wide.
+struct Glyphs {
+    current: &'static str,
+    pinned: &'static str,
+    /// Marked with Space for deletion.
+    marked: &'static str,
+    pr: &'static str,
+    merge_request: &'static str,
+    /// Merged into the default branch, or a merged PR.
+    merged: &'static str,
+    ahead: &'static str,
+    behind: &'static str,
+    diverged: &'static str,
+    gone: &'static str,
+    worktree: &'static str,
+    /// The tip commit is signed; colored by whether the signature is good.
+    signed: &'static str,
+    /// Tracking info reads "ahead 2, behind 1" rather than being made of
+    /// the `ahead` and `behind` glyphs.
+    spelled_out: bool,
+}
+
+/// Plain text, nothing that terminals might draw as an emoji.
+const DEFAULT_GLYPHS: Glyphs = Glyphs {
+    current: "* ",
+    pinned: "^ ",
+    marked: "+ ",
+    pr: "#",
+    merge_request: "!",
+    merged: "✓",
+    ahead: "↑",
+    behind: "↓",
+    diverged: "↑↓",
+    gone: "✗",
+    worktree: "[wt]",
+    signed: "S",
+    spelled_out: true,
+};
+
+/// For `--icons`, from Nerd Fonts' Octicons.
+const NERD_FONT_GLYPHS: Glyphs = Glyphs {
+    current: "\u{f418} ",
+    pinned: "\u{f435} ",
+    marked: "\u{f48e} ",
+    pr: "\u{f407} ",
+    merge_request: "\u{f296} ",
+    merged: "\u{f419}",
+    ahead: "\u{f431}",
+    behind: "\u{f433}",
+    diverged: "\u{f4dc}",
+    gone: "\u{f467}",
+    worktree: "\u{f413}",
+    signed: "\u{f023}",
+    spelled_out: false,
+};
+
+impl Glyphs {
+    fn pr_sigil(&self, provider: PrProvider) -> &'static str {
+        match provider {
+            PrProvider::GitHub => self.pr,
+            PrProvider::GitLab => self.merge_request,
+        }
+    }
+
+    /// `tracking_info` ("ahead 2, behind 1", "gone", ...) in these glyphs.
+    fn tracking<'a>(&self, info: &'a str) -> Cow<'a, str> {
+        if self.spelled_out {
+            return info.into();
+        }
+        info.split(", ")
+            .map(|part| {
+                if let Some(n) = part.strip_prefix("ahead ") {
+                    format!("{}{}", self.ahead, n)
+                } else if let Some(n) = part.strip_prefix("behind ") {
+                    format!("{}{}", self.behind, n)
+                } else if part == "gone" {
+                    self.gone.to_string()
+                } else {
+                    part.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+            .into()
+    }
+
+    /// What compact rows show of the tracking info: whether the branch is
+    /// ahead of, behind or diverged from its upstream, or the upstream is
+    /// gone.
+    fn tracking_indicator(&self, b: &BranchInfo) -> &'static str {
+        let info = b.tracking_info.as_str();
+        if b.is_upstream_gone() {
+            self.gone
+        } else if info.starts_with("ahead") && info.contains("behind") {
+            self.diverged
+        } else if info.starts_with("ahead") {
+            self.ahead
+        } else if info.starts_with("behind") {
+            self.behind
+        } else {
+            ""
+        }
+    }
+}
+
+/// The tracking column of a row: ahead/behind counts and, with `--verbose`,
+/// the upstream's name.
+fn tracking_label<'a>(b: &'a BranchInfo, verbose: bool, glyphs: &Glyphs) -> Cow<'a, str> {
+    let info = glyphs.tracking(&b.tracking_info);
+    match &b.upstream {
+        Some(upstream) if verbose && info.is_empty() => upstream.into(),
+        Some(upstream) if verbose => format!("{} {}", upstream, info).into(),
+        _ => info,
+    }
+}
+
+/// Width of the tracking label plus the worktree marker, if any.
+fn tracking_column_width(b: &BranchInfo, verbose: bool, glyphs: &Glyphs) -> usize {
+    let tracking = tracking_label(b, verbose, glyphs).width();
+    let marker = glyphs.worktree.width();
+    match (&b.worktree, tracking) {
+        (None, _) => tracking,
+        (Some(_), 0) => marker,
+        (Some(_), _) => tracking + 1 + marker,
+    }
+}
+
+/// Shortens `s` to fit in `max_width` columns, marking the cut with an ellipsis.
+fn truncate(s: &str, max_width: usize) -> String {
+    if s.width() <= max_width {
+        return s.to_string();
+    }
+    let mut result = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max_width {
+            break;
+        }
+        result.push(c);
+        width += w;
+    }
+    if max_width > 0 {
+        result.push('…');
+    }
+    result
+}
+
+/// Below this many columns rows are compact as if `--compact` were given.
+const COMPACT_MAX_WIDTH: u16 = 50;
+
+/// The preview pane is only shown when the terminal is at least this wide.
+const PREVIEW_MIN_WIDTH: u16 = 80;
+
+fn ui(f: &mut Frame, app: &mut App) {
+    if app.branches.is_empty() {
+        let text = "No git branches found in this directory.";
+        let block = Block::default().title("Error").borders(Borders::ALL);
+        let paragraph = Paragraph::new(text).block(block);
+        f.render_widget(paragraph, f.area());
+        return;
+    }
+
+    let [main_area, footer_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(f.area());
+    f.render_widget(footer(app), footer_area);
+
+    let main_area = match &app.git_error {
+        Some((title, err)) => {
+            // Keep at least half the rows for the list
+            let height = (err.lines().count() as u16 + 2).min(main_area.height / 2);
+            let [banner_area, main_area] =
+                Layout::vertical([Constraint::Length(height), Constraint::Min(0)]).areas(main_area);
+            let banner = Paragraph::new(err.as_str())
+                .wrap(Wrap { trim: false })
+                .style(Style::default().fg(app.theme.danger))
+                .block(Block::default().borders(Borders::ALL).title(*title));
+            f.render_widget(banner, banner_area);
+            main_area
+        }
+        None => main_area,
+    };
+
+    let (list_area, preview_area) = if main_area.width >= PREVIEW_MIN_WIDTH {
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(main_area);
+        (list_area, Some(preview_area))
+    } else {
+        (main_area, None)
+    };
+
+    app.preview_width = preview_area.map_or(0, |area| area.width.saturating_sub(2));
+    if let Some(preview_area) = preview_area {
+        let name = app.selected_branch().map(|b| b.rev().to_string());
+        let title = if app.show_diff_stat {
+            "Changes against the default branch".to_string()
+        } else {
+            match app.selected_branch().and_then(|b| b.upstream.as_deref()) {
+                Some(upstream) => format!("Commits (tracking {})", upstream),
+                None => "Commits".to_string(),
+            }
+        };
+        let hash_style = Style::default().fg(app.theme.date);
+        let mut lines: Vec<Line> = match &name {
+            Some(name) if app.show_diff_stat => {
+                let dim = Style::default().add_modifier(Modifier::DIM);
+                match app.diff_stat_cache.get(name) {
+                    // At another width it's about to be redone
+                    Some((width, _)) if *width != app.preview_width => {
+                        vec![Line::styled("(loading…)", dim)]
+                    }
+                    None => vec![Line::styled("(loading…)", dim)],
+                    Some((_, None)) => vec![Line::styled("(no default branch found)", dim)],
+                    Some((_, Some(stat))) => match stat.split_last() {
+                        None => vec![Line::styled("(no changes)", dim)],
+                        // The last line sums up the files, insertions and
+                        // deletions
+                        Some((summary, files)) => files
+                            .iter()
+                            .map(|file| Line::raw(file.clone()))
+                            .chain([Line::styled(summary.clone(), dim)])
+                            .collect(),
+                    },
+                }
+            }
+            Some(name) => match app.commit_cache.get(name) {
+                None => vec![Line::styled(
+                    "(loading…)",
+                    Style::default().add_modifier(Modifier::DIM),
+                )],
+                Some(commits) if commits.is_empty() => vec![Line::styled(
+                    "(no commits)",
+                    Style::default().add_modifier(Modifier::DIM),
+                )],
+                Some(commits) => commits
+                    .iter()
+                    .map(|c| match c.split_once(' ') {
+                        Some((hash, subject)) => Line::from(vec![
+                            Span::styled(hash.to_string(), hash_style),
+                            Span::raw(" "),
+                            Span::raw(subject.to_string()),
+                        ]),
+                        None => Line::raw(c.clone()),
+                    })
+                    .collect(),
+            },
+            None => Vec::new(),
+        };
+        if let Some(description) = app.selected_branch().and_then(|b| b.description.as_deref()) {
+            let style = Style::default().add_modifier(Modifier::ITALIC);
+            let mut top: Vec<Line> = description
+                .lines()
+                .map(|line| Line::styled(line.to_string(), style))
+                .collect();
+            top.push(Line::raw(""));
+            lines.splice(0..0, top);
+        }
+        let preview =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(preview, preview_area);
     }
 
-    let items: Vec<ListItem> = app
-        .branches
+    // Branches are indented under their group header
+    let indent = if app.group_by_prefix { "  " } else { "" };
+    // Numbers are right-aligned, followed by a space
+    let number_width = app.filtered_indices.len().to_string().len();
+    let numbers_width = if app.line_numbers {
+        number_width + 1
+    } else {
+        0
+    };
+    // 2 for borders, 2 for the highlight symbol
+    let row_width = (list_area.width as usize).saturating_sub(4 + indent.len() + numbers_width);
+
+    let theme = &app.theme;
+    let glyphs = app.glyphs;
+    let now = unix_now();
+    let pinned: HashSet<&str> = app
+        .pinned_in_config
+        .iter()
+        .chain(&app.pinned)
+        .map(String::as_str)
+        .collect();
+    // Fuzzy filtering already shows only matches, so only jumping and
+    // regexes mark them
+    let search_match = |name: &str| match &app.search_regex {
+        Some(regex) => regex.find(name).map(|m| m.range()),
+        None if app.search_mode == SearchMode::Jump && !app.regex_search => {
+            find_ignore_case(name, &app.search_query)
+        }
+        None => None,
+    };
+    // Size the right-hand columns by the longest entry among the shown
+    // branches so they line up from row to row
+    let shown_branches = || app.filtered_indices.iter().map(|&i| &app.branches[i]);
+    let date_column = shown_branches()
+        .map(|b| b.last_commit_date.width())
+        .max()
+        .unwrap_or(0);
+    let tracking_column = shown_branches()
+        .map(|b| tracking_column_width(b, app.verbose, glyphs))
+        .max()
+        .unwrap_or(0);
+    let right_fixed = date_column
+        + if tracking_column > 0 {
+            tracking_column + 1
+        } else {
+            0
+        };
+    // Narrow terminals only get the name and a tracking arrow per row, as
+    // do lists whose columns would leave next to no room for names
+    let name_room = row_width.saturating_sub(right_fixed + 3);
+    let compact = app.compact || f.area().width < COMPACT_MAX_WIDTH || name_room < NAME_MIN_WIDTH;
+    let author_column = shown_branches()
+        .map(|b| b.last_author.width())
+        .max()
+        .unwrap_or(0)
+        .min(AUTHOR_MAX_WIDTH)
+        .min(name_room / 3);
+    let right_width = right_fixed
+        + if author_column > 0 {
+            author_column + 1
+        } else {
+            0
+        };
+
+    let rows = app.list_rows();
+    let items: Vec<ListItem> = rows
         .iter()
-        .map(|b| {
-            let (line_style, name_style) = if !b.has_upstream || b.tracking_info.contains("gone") {
+        .map(|row| {
+            let pos = match *row {
+                ListRow::Header(prefix) => {
+                    let label = match prefix {
+                        Some(prefix) => format!("{}/", prefix),
+                        None => "(no prefix)".to_string(),
+                    };
+                    return ListItem::new(Line::styled(
+                        truncate(&label, row_width + indent.len() + numbers_width),
+                        Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    ));
+                }
+                ListRow::Branch(pos) => pos,
+            };
+            let number = if app.line_numbers {
+                Span::styled(
+                    format!("{:>width$} ", pos + 1, width = number_width),
+                    Style::default().add_modifier(Modifier::DIM),
+                )
+            } else {
+                Span::raw("")
+            };
+            let i = app.filtered_indices[pos];
+            let b = &app.branches[i];
+            let (line_style, name_style) = if b.is_tag() {
+                (Style::default(), Style::default().fg(theme.tag))
+            } else if b.remote_ref.is_some() {
+                (
+                    Style::default(),
+                    Style::default()
+                        .fg(theme.remote)
+                        .add_modifier(Modifier::DIM),
+                )
+            } else if b.is_upstream_gone() {
+                // Most likely merged and cleaned up on the remote
                 (
                     Style::default().add_modifier(Modifier::DIM),
+                    Style::default()
+                        .fg(theme.gone)
+                        .add_modifier(Modifier::CROSSED_OUT),
+                )
+            } else if b.is_gone() {
+                // Never pushed: either push it or it's a local experiment
+                (
                     Style::default(),
+                    Style::default()
+                        .fg(theme.local)
+                        .add_modifier(Modifier::BOLD | Modifier::ITALIC),
                 )
             } else {
                 (
@@ -359,41 +4163,828 @@ fn ui(f: &mut Frame, app: &mut App) {
                 )
             };
 
-            let prefix_style = Style::default().fg(Color::Green);
-            let date_style = Style::default().fg(Color::Yellow);
-            let tracking_style = Style::default().fg(Color::Cyan);
-
-            let pr_span = if let Some(pr_number) = b.pr_number {
-                Span::styled(
-                    format!(" #{}", pr_number),
-                    Style::default().fg(Color::Magenta),
+            let is_marked = app.selected_for_action.contains(&i);
+            let (prefix, prefix_style) = if b.is_current {
+                (glyphs.current, Style::default().fg(theme.current))
+            } else if is_marked {
+                (
+                    glyphs.marked,
+                    Style::default()
+                        .fg(theme.danger)
+                        .add_modifier(Modifier::BOLD),
                 )
+            } else if b.ref_type == RefType::Branch && pinned.contains(b.name.as_str()) {
+                (glyphs.pinned, Style::default().fg(theme.current))
             } else {
-                Span::raw("")
+                ("  ", Style::default())
             };
+            let date_style = theme.date_style(now - b.last_commit_timestamp);
+            let tracking_style = Style::default().fg(theme.tracking);
+            let author_style = Style::default().fg(theme.author);
 
-            let line = Line::from(vec![
-                Span::styled(if b.is_current { "* " } else { "  " }, prefix_style),
-                Span::styled(&b.name, name_style),
-                pr_span,
-                Span::raw(" ("),
-                Span::styled(&b.last_commit_date, date_style),
-                Span::raw(") "),
-                Span::styled(&b.tracking_info, tracking_style),
-            ])
-            .set_style(line_style);
-            ListItem::new(line)
+            let pr_span = match b.pr {
+                Some(pr) => {
+                    let sigil = glyphs.pr_sigil(pr.provider);
+                    let (text, style) = match pr.state {
+                        PrState::Open if pr.is_draft => (
+                            format!(" {}{}", sigil, pr.number),
+                            Style::default().fg(theme.pr_draft),
+                        ),
+                        PrState::Open => (
+                            format!(" {}{}", sigil, pr.number),
+                            Style::default().fg(theme.pr),
+                        ),
+                        PrState::Merged => (
+                            format!(" {}{}{}", sigil, pr.number, glyphs.merged),
+                            Style::default().fg(theme.pr_merged),
+                        ),
+                        PrState::Closed => (
+                            format!(" {}{}", sigil, pr.number),
+                            Style::default()
+                                .fg(theme.pr_closed)
+                                .add_modifier(Modifier::CROSSED_OUT),
+                        ),
+                    };
+                    Span::styled(text, style)
+                }
+                None => Span::raw(""),
+            };
+
+            let tracking = tracking_label(b, app.verbose, glyphs);
+
+            if compact {
+                let indicator = glyphs.tracking_indicator(b);
+                let name_width = row_width.saturating_sub(if indicator.is_empty() {
+                    2
+                } else {
+                    3 + indicator.width()
+                });
+                let mut spans = vec![
+                    Span::raw(indent),
+                    number,
+                    Span::styled(prefix, prefix_style),
+                ];
+                spans.extend(name_spans(
+                    truncate(&b.name, name_width),
+                    name_style,
+                    search_match,
+                    theme.search_match,
+                ));
+                if !indicator.is_empty() {
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(indicator, tracking_style));
+                }
+                return ListItem::new(Line::from(spans).set_style(line_style))
+                    .style(current_row_style(theme, b));
+            }
+
+            let ahead_badge = b
+                .commits_ahead_of_main
+                .filter(|&n| n > 0)
+                .map(|n| format!(" +{}", n));
+
+            // The name, badges and subject sit on the left; the date, author
+            // and tracking info are right-aligned in columns
+            let worktree_marker = if b.worktree.is_some() {
+                glyphs.worktree
+            } else {
+                ""
+            };
+            let signature_span = match b.signature {
+                Some(SignatureStatus::Good) => Span::styled(
+                    format!(" {}", glyphs.signed),
+                    Style::default().fg(theme.current),
+                ),
+                Some(SignatureStatus::Bad) => Span::styled(
+                    format!(" {}", glyphs.signed),
+                    Style::default().fg(theme.danger),
+                ),
+                Some(SignatureStatus::Unverified) => Span::styled(
+                    format!(" {}", glyphs.signed),
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+                Some(SignatureStatus::Unsigned) | None => Span::raw(""),
+            };
+            let merged_width = if b.is_merged {
+                1 + glyphs.merged.width()
+            } else {
+                0
+            };
+            let left_fixed = 2
+                + ahead_badge.as_deref().map_or(0, str::len)
+                + merged_width
+                + signature_span.width()
+                + pr_span.width();
+            // One column of gap between the two sides at the very least
+            let name_width = row_width.saturating_sub(left_fixed + right_width + 1);
+            // The subject only gets what the name doesn't need, so it's the
+            // first thing to go on narrow terminals
+            let subject_width = name_width.saturating_sub(b.name.width()).saturating_sub(1);
+
+            let mut spans = vec![
+                Span::raw(indent),
+                number,
+                Span::styled(prefix, prefix_style),
+            ];
+            let name = truncate(&b.name, name_width);
+            let mut left_width = 2 + name.width();
+            spans.extend(name_spans(
+                name,
+                name_style,
+                search_match,
+                theme.search_match,
+            ));
+            if let Some(ahead) = ahead_badge {
+                spans.push(Span::styled(
+                    ahead,
+                    Style::default()
+                        .fg(theme.tracking)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+            if b.is_merged {
+                spans.push(Span::styled(
+                    format!(" {}", glyphs.merged),
+                    Style::default()
+                        .fg(theme.pr_merged)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+            spans.push(signature_span);
+            spans.push(pr_span);
+            left_width += left_fixed - 2;
+            if subject_width >= SUBJECT_MIN_WIDTH && !b.last_subject.is_empty() {
+                let subject = truncate(&b.last_subject, subject_width);
+                left_width += 1 + subject.width();
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    subject,
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+
+            let padding = row_width.saturating_sub(left_width + right_width).max(1)
+                + date_column.saturating_sub(b.last_commit_date.width());
+            spans.push(Span::raw(" ".repeat(padding)));
+            spans.push(Span::styled(&b.last_commit_date, date_style));
+            if author_column > 0 {
+                let author = truncate(&b.last_author, author_column);
+                let fill = author_column - author.width();
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(author, author_style));
+                spans.push(Span::raw(" ".repeat(fill)));
+            }
+            if tracking_column > 0 {
+                let fill = tracking_column - tracking_column_width(b, app.verbose, glyphs);
+                spans.push(Span::raw(" ".repeat(fill + 1)));
+                let has_tracking = !tracking.is_empty();
+                spans.push(Span::styled(tracking, tracking_style));
+                if !worktree_marker.is_empty() {
+                    if has_tracking {
+                        spans.push(Span::raw(" "));
+                    }
+                    spans.push(Span::styled(
+                        worktree_marker,
+                        Style::default().add_modifier(Modifier::DIM),
+                    ));
+                }
+            }
+
+            let line = Line::from(spans).set_style(line_style);
+            ListItem::new(line).style(current_row_style(theme, b))
         })
         .collect();
 
+    let shown = app.filtered_indices.len();
+    let count = if shown < app.branches.len() {
+        format!("{}/{}", shown, app.branches.len())
+    } else {
+        app.branches.len().to_string()
+    };
+    let mut title = format!("Branches ({}) [{}]", count, app.sort_mode.label());
+    if app.hidden_by_limit > 0 {
+        title.push_str(&format!(
+            " (showing {} of {})",
+            app.branches.len(),
+            app.branches.len() + app.hidden_by_limit
+        ));
+    }
+    if let Some(since) = &app.list_options.since {
+        title.push_str(&format!(" (since {})", since));
+    }
+    if app.hide_gone {
+        title.push_str(" (hiding gone)");
+    }
+    if app.hide_remotes {
+        title.push_str(" (hiding remotes)");
+    }
+    match app.pr_filter {
+        PrFilter::All => {}
+        PrFilter::WithPr => title.push_str(" (with PR)"),
+        PrFilter::ReadyForReview => title.push_str(" (open PRs)"),
+    }
+    if app.group_by_prefix {
+        title.push_str(" (grouped)");
+    }
+    if app.searching || !app.search_query.is_empty() {
+        title.push_str(&format!(" /{}", app.search_query));
+        if app.regex_search {
+            title.push_str(" [regex]");
+        }
+        if let Some(err) = &app.regex_error {
+            title.push_str(&format!(" ({})", err));
+        }
+        if app.search_mode == SearchMode::Jump && !app.search_query.is_empty() {
+            title.push_str(&match app.search_matches.len() {
+                0 => " (no match)".to_string(),
+                n => format!(" ({}/{})", app.current_match + 1, n),
+            });
+        }
+    }
+
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if let Some(hash) = &app.detached_head {
+        // No row is marked current, so say where HEAD actually is
+        block = block.title(Line::styled(
+            format!("(detached HEAD at {})", hash),
+            Style::default()
+                .fg(app.theme.current)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(fetch) = &app.pr_fetch {
+        const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+        let frame = (fetch.started.elapsed().as_millis() / 100) as usize % SPINNER.len();
+        block = block.title(
+            Line::styled(
+                format!(" {} loading PRs… ", SPINNER[frame]),
+                Style::default().add_modifier(Modifier::DIM),
+            )
+            .right_aligned(),
+        );
+    } else if app.pr_timed_out {
+        block = block.title(
+            Line::styled(
+                " PRs timed out ",
+                Style::default().add_modifier(Modifier::DIM),
+            )
+            .right_aligned(),
+        );
+    }
+    if let Some(index) = app.pending_delete {
+        let name = &app.branches[index].name;
+        let prompt = match &app.delete_error {
+            Some(err) => format!(
+                " {} Force delete '{}'? (D/n) ",
+                err.lines().next().unwrap_or_default(),
+                name
+            ),
+            None => format!(" Delete branch '{}'? (y/n) ", name),
+        };
+        block = block.title_bottom(Line::styled(prompt, Style::default().fg(app.theme.danger)));
+    } else if let Some(branch) = app.selected_branch().filter(|_| app.pending_track_checkout) {
+        let prompt = format!(
+            " Create local '{}' tracking '{}'? (y/n) ",
+            branch.name,
+            branch.remote_ref.as_deref().unwrap_or_default()
+        );
+        block = block.title_bottom(Line::styled(prompt, Style::default().fg(app.theme.prompt)));
+    } else if app.pending_batch_delete {
+        let prompt = format!(
+            " Force delete {} marked branches? (y/n) ",
+            app.selected_for_action.len()
+        );
+        block = block.title_bottom(Line::styled(prompt, Style::default().fg(app.theme.danger)));
+    } else if !app.number_input.is_empty() {
+        block = block.title_bottom(Line::from(vec![
+            Span::styled(" Go to: ", Style::default().fg(app.theme.prompt)),
+            Span::raw(format!("{}_ ", app.number_input)),
+        ]));
+    } else if let Some(input) = &app.input {
+        block = block.title_bottom(Line::from(vec![
+            Span::styled(
+                format!(" {}: ", input.kind.prompt()),
+                Style::default().fg(app.theme.prompt),
+            ),
+            Span::raw(format!("{}_ ", input.buffer)),
+        ]));
+    } else if let Some(message) = &app.status_message {
+        block = block.title_bottom(format!(" {} ", message.lines().next().unwrap_or_default()));
+    }
+    let total = app.filtered_indices.len();
+    if let Some(selected) = app.state.selected().filter(|_| total > 0) {
+        block =
+            block.title_bottom(Line::from(format!(" {}/{} ", selected + 1, total)).right_aligned());
+    }
+
     let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title("Branches"))
+        .block(block)
         .highlight_style(
             Style::default()
                 .add_modifier(Modifier::REVERSED)
-                .fg(Color::Green),
+                .fg(app.theme.highlight),
         )
         .highlight_symbol("> ");
 
-    f.render_stateful_widget(list, f.area(), &mut app.state);
+    // Selection is kept in branch positions, but the widget counts headers
+    let row_count = rows.len();
+    let selected_row = app.state.selected().and_then(|selected| {
+        rows.iter()
+            .position(|row| matches!(row, ListRow::Branch(pos) if *pos == selected))
+    });
+    // A taller list after a resize may fit rows that were scrolled past
+    let max_offset = row_count.saturating_sub(list_area.height.saturating_sub(2) as usize);
+    let mut list_state = ListState::default()
+        .with_offset(app.state.offset().min(max_offset))
+        .with_selected(selected_row);
+    app.list_area = list_area;
+    f.render_stateful_widget(list, list_area, &mut list_state);
+    *app.state.offset_mut() = list_state.offset();
+
+    let visible_rows = app.visible_rows();
+    if row_count > visible_rows {
+        let mut scrollbar_state = ScrollbarState::new(row_count.saturating_sub(visible_rows))
+            .position(app.state.offset())
+            .viewport_content_length(visible_rows);
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None),
+            list_area.inner(Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut scrollbar_state,
+        );
+    }
+
+    if app.show_help {
+        render_help(f, &app.keys, &app.theme);
+    } else if app.pending_dirty_checkout.is_some() {
+        render_dirty_menu(f, &app.theme);
+    }
+}
+
+/// Asks what to do with uncommitted changes before a checkout.
+fn render_dirty_menu(f: &mut Frame, theme: &Theme) {
+    let choices = [
+        ("1 s", "Stash changes, then switch"),
+        (
+            "2 c",
+            "Switch anyway (git carries changes over, or refuses)",
+        ),
+        ("3 q", "Cancel"),
+    ];
+    let lines: Vec<Line> = choices
+        .iter()
+        .map(|(keys, choice)| {
+            Line::from(vec![
+                Span::styled(format!(" {}  ", keys), Style::default().fg(theme.prompt)),
+                Span::raw(format!("{} ", choice)),
+            ])
+        })
+        .collect();
+    let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 2;
+    let popup = centered_rect(f.area(), width, lines.len() as u16 + 2);
+    let menu = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.danger))
+            .title("Uncommitted changes"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(menu, popup);
+}
+
+/// A `width` by `height` rectangle in the middle of `area`, shrunk to fit.
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Key hints for whatever mode the app is in.
+fn footer(app: &App) -> Line<'static> {
+    let keys = &app.keys;
+    let hints: Vec<(String, &str)> = if app.show_help {
+        vec![("any key".to_string(), "close help")]
+    } else if app.pending_delete.is_some() {
+        let confirm = if app.delete_error.is_some() { "D" } else { "y" };
+        vec![
+            (confirm.to_string(), "delete"),
+            ("any other key".to_string(), "cancel"),
+        ]
+    } else if app.pending_dirty_checkout.is_some() {
+        vec![
+            ("1/s".to_string(), "stash and switch"),
+            ("2/c".to_string(), "switch anyway"),
+            ("any other key".to_string(), "back to list"),
+        ]
+    } else if app.pending_track_checkout {
+        vec![
+            ("y".to_string(), "create and checkout"),
+            ("any other key".to_string(), "back to list"),
+        ]
+    } else if app.pending_batch_delete {
+        vec![
+            ("y".to_string(), "delete"),
+            ("any other key".to_string(), "cancel"),
+        ]
+    } else if app.input.is_some() {
+        vec![
+            ("Enter".to_string(), "confirm"),
+            ("Esc".to_string(), "cancel"),
+        ]
+    } else if !app.number_input.is_empty() {
+        vec![
+            ("Enter".to_string(), "go to branch"),
+            ("Esc".to_string(), "cancel"),
+        ]
+    } else if app.type_to_filter {
+        vec![
+            ("type".to_string(), "filter"),
+            ("↑↓".to_string(), "move"),
+            ("Enter".to_string(), "checkout"),
+            (
+                "Esc".to_string(),
+                if app.search_query.is_empty() {
+                    "quit"
+                } else {
+                    "clear"
+                },
+            ),
+        ]
+    } else if app.searching && app.search_mode == SearchMode::Jump {
+        vec![
+            ("type".to_string(), "find"),
+            ("Ctrl-t".to_string(), "regex"),
+            ("Enter".to_string(), "done"),
+            ("Esc".to_string(), "clear"),
+        ]
+    } else if app.searching {
+        vec![
+            ("type".to_string(), "filter"),
+            ("Ctrl-t".to_string(), "regex"),
+            ("↑↓".to_string(), "move"),
+            ("Enter".to_string(), "checkout"),
+            ("Esc".to_string(), "clear"),
+        ]
+    } else if !app.search_matches.is_empty() {
+        vec![
+            ("n/N".to_string(), "next/previous match"),
+            (keys.describe(Action::Checkout), "checkout"),
+            ("Esc".to_string(), "clear search"),
+        ]
+    } else {
+        vec![
+            ("↑↓".to_string(), "move"),
+            (keys.describe(Action::Checkout), "checkout"),
+            (keys.describe(Action::Delete), "delete"),
+            ("/".to_string(), "search"),
+            ("?".to_string(), "help"),
+            (keys.describe(Action::Quit), "quit"),
+        ]
+    };
+
+    let mut spans = Vec::new();
+    for (key, action) in hints {
+        spans.push(Span::styled(
+            format!(" {} ", key),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        spans.push(Span::styled(
+            format!("{} ", action),
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+    }
+    let browsing = !app.is_prompting() && !app.searching;
+    if browsing && app.stash_count > 0 {
+        spans.push(Span::styled(
+            match app.stash_count {
+                1 => " 1 stash".to_string(),
+                n => format!(" {} stashes", n),
+            },
+            Style::default().fg(app.theme.date),
+        ));
+    }
+    Line::from(spans)
+}
+
+fn render_help(f: &mut Frame, keys: &KeyConfig, theme: &Theme) {
+    let entries = [
+        (
+            format!(
+                "{} {}",
+                keys.describe(Action::Previous),
+                keys.describe(Action::Next)
+            ),
+            "move selection",
+        ),
+        ("PageUp PageDown".to_string(), "move by a page"),
+        ("Ctrl-u Ctrl-d".to_string(), "move by half a page"),
+        ("Home End/G".to_string(), "first / last branch"),
+        (".".to_string(), "jump to current branch"),
+        ("-".to_string(), "checkout previous branch"),
+        ("w".to_string(), "checkout in a new worktree"),
+        (keys.describe(Action::Checkout), "checkout branch"),
+        ("/".to_string(), "search (Esc clears)"),
+        ("n N".to_string(), "next / previous match (jump search)"),
+        ("n".to_string(), "new branch off HEAD"),
+        ("R".to_string(), "rename branch"),
+        ("v".to_string(), "preview commits / diff stat"),
+        ("e".to_string(), "edit branch description"),
+        ("c".to_string(), "checkout a commit (detached)"),
+        (keys.describe(Action::Delete), "delete branch"),
+        ("Space".to_string(), "mark branch"),
+        ("D".to_string(), "delete marked branches"),
+        ("s".to_string(), "cycle sort order"),
+        ("T".to_string(), "cycle date format"),
+        ("g".to_string(), "hide gone branches"),
+        ("r".to_string(), "hide remote-only branches"),
+        ("p".to_string(), "only branches with (open) PRs"),
+        ("t".to_string(), "group by prefix"),
+        ("o".to_string(), "open PR in browser"),
+        ("y".to_string(), "copy branch name"),
+        ("f".to_string(), "pin / unpin branch"),
+        ("P".to_string(), "fast-forward branch to upstream"),
+        ("u".to_string(), "push branch and set upstream"),
+        ("l L".to_string(), "show branch's git log in the pager"),
+        (keys.describe(Action::Refresh), "reload branches and PRs"),
+        ("?".to_string(), "toggle this help"),
+        (keys.describe(Action::Quit), "quit"),
+    ];
+    let key_width = entries.iter().map(|(k, _)| k.width()).max().unwrap_or(0);
+    let action_width = entries.iter().map(|(_, a)| a.width()).max().unwrap_or(0);
+    let column_width = key_width + action_width + 4;
+
+    // The inline viewport is often short, so flow entries into columns.
+    let area = f.area();
+    let rows = (area.height.saturating_sub(2) as usize)
+        .max(1)
+        .min(entries.len());
+    let columns = entries.len().div_ceil(rows);
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let mut spans = Vec::new();
+            for (key, action) in entries.iter().skip(row).step_by(rows) {
+                spans.push(Span::styled(
+                    format!(" {:<width$}  ", key, width = key_width),
+                    Style::default().fg(theme.prompt),
+                ));
+                spans.push(Span::raw(format!(
+                    "{:<width$} ",
+                    action,
+                    width = action_width
+                )));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    let popup = centered_rect(area, (column_width * columns) as u16 + 2, rows as u16 + 2);
+
+    let help = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Help (press any key)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(help, popup);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+
+    fn init_repo() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let sig = Signature::now("Tester", "tester@example.com").unwrap();
+            let tree_id = repo.index().unwrap().write_tree().unwrap();
+            let tree = repo.find_tree(tree_id).unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])
+                .unwrap();
+        }
+        (dir, repo)
+    }
+
+    fn add_commit(repo: &Repository, parent: git2::Oid, message: &str) -> git2::Oid {
+        let sig = Signature::now("Tester", "tester@example.com").unwrap();
+        let parent = repo.find_commit(parent).unwrap();
+        let tree = parent.tree().unwrap();
+        repo.commit(None, &sig, &sig, message, &tree, &[&parent])
+            .unwrap()
+    }
+
+    fn find<'a>(branches: &'a [BranchInfo], name: &str) -> &'a BranchInfo {
+        branches
+            .iter()
+            .find(|b| b.name == name)
+            .unwrap_or_else(|| panic!("'{}' not listed", name))
+    }
+
+    #[test]
+    fn lists_branch_with_pipe_in_name() {
+        let (_dir, repo) = init_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("feat|foo", &head, false).unwrap();
+
+        let branches = collect_branches(&repo).unwrap();
+        let names: Vec<&str> = branches.iter().map(|b| b.name.as_str()).collect();
+        assert!(names.contains(&"feat|foo"), "{:?}", names);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_raw_bytes_of_non_utf8_name() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let (dir, repo) = init_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap().id();
+        // libgit2 only creates refs from UTF-8 names, so write a loose ref
+        let name = b"caf\xe9".to_vec();
+        let path = dir
+            .path()
+            .join(".git/refs/heads")
+            .join(OsStr::from_bytes(&name));
+        std::fs::write(path, format!("{}\n", head)).unwrap();
+
+        let branches = collect_branches(&repo).unwrap();
+        let branch = find(&branches, "caf\u{fffd}");
+        assert_eq!(branch.raw_name, OsString::from_vec(name));
+        assert_eq!(
+            join_os("refs/heads/", &branch.raw_name).as_bytes(),
+            b"refs/heads/caf\xe9"
+        );
+    }
+
+    #[test]
+    fn renaming_checks_out_the_new_name() {
+        let (_dir, repo) = init_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("foo", &head, false).unwrap();
+        let branches = collect_branches(&repo)
+            .unwrap()
+            .into_iter()
+            .filter(|b| b.name == "foo")
+            .collect();
+        let mut app = App::new(
+            branches,
+            None,
+            KeyConfig::default(),
+            Theme::default(),
+            SortMode::Date,
+        );
+        app.dry_run = true;
+
+        app.rename(0, "bar");
+        assert_eq!(app.branches[0].name, "bar");
+        assert_eq!(app.branches[0].raw_name, OsString::from("bar"));
+    }
+
+    #[test]
+    fn reads_tracking_state() {
+        let (_dir, repo) = init_repo();
+        let init = repo.head().unwrap().peel_to_commit().unwrap().id();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+
+        // Ahead of an upstream that still exists
+        let ahead = add_commit(&repo, init, "ahead");
+        repo.branch("tracked", &repo.find_commit(ahead).unwrap(), false)
+            .unwrap();
+        repo.reference("refs/remotes/origin/tracked", init, false, "")
+            .unwrap();
+        // Upstream configured, but its remote branch was deleted
+        repo.branch("gone", &repo.find_commit(init).unwrap(), false)
+            .unwrap();
+        // Never pushed
+        repo.branch("local", &repo.find_commit(init).unwrap(), false)
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        for name in ["tracked", "gone"] {
+            config
+                .set_str(&format!("branch.{}.remote", name), "origin")
+                .unwrap();
+            config
+                .set_str(
+                    &format!("branch.{}.merge", name),
+                    &format!("refs/heads/{}", name),
+                )
+                .unwrap();
+        }
+        // Only on the remote
+        repo.reference("refs/remotes/origin/theirs", ahead, false, "")
+            .unwrap();
+
+        let branches = collect_branches(&repo).unwrap();
+
+        let tracked = find(&branches, "tracked");
+        assert_eq!(tracked.tracking_info, "ahead 1");
+        assert!(tracked.has_upstream && !tracked.is_gone());
+        assert_eq!(tracked.upstream.as_deref(), Some("origin/tracked"));
+        assert_eq!(tracked.last_subject, "ahead");
+
+        let gone = find(&branches, "gone");
+        assert_eq!(gone.tracking_info, "gone");
+        assert!(gone.has_upstream && gone.is_gone());
+
+        let local = find(&branches, "local");
+        assert_eq!(local.tracking_info, "");
+        assert!(!local.has_upstream && local.is_gone());
+        assert_eq!(local.upstream, None);
+
+        let theirs = find(&branches, "theirs");
+        assert_eq!(theirs.remote_ref.as_deref(), Some("origin/theirs"));
+        assert_eq!(theirs.tracking_info, "remote");
+
+        let current: Vec<&str> = branches
+            .iter()
+            .filter(|b| b.is_current)
+            .map(|b| b.name.as_str())
+            .collect();
+        let head = repo.head().unwrap();
+        assert_eq!(current, [head.shorthand().unwrap()]);
+    }
+
+    #[test]
+    fn navigates_empty_list() {
+        let mut app = App::new(
+            Vec::new(),
+            None,
+            KeyConfig::default(),
+            Theme::default(),
+            SortMode::Date,
+        );
+        app.next();
+        app.previous();
+        app.next_page();
+        app.prev_page();
+        app.first();
+        app.last();
+        assert_eq!(app.state.selected(), None);
+        assert!(app.selected_branch().is_none());
+
+        // Also with everything filtered out after a selection
+        app.state.select(Some(3));
+        app.next();
+        app.previous();
+        app.next_page();
+        app.last();
+        assert_eq!(app.state.selected(), Some(3));
+    }
+
+    #[test]
+    fn parses_pr_list() {
+        let json = br#"[
+            {"headRefName": "feature", "number": 3, "state": "MERGED", "isDraft": false},
+            {"headRefName": "feature", "number": 5, "state": "OPEN", "isDraft": true},
+            {"headRefName": "feature", "number": 4, "state": "CLOSED", "isDraft": false},
+            {"headRefName": "fix", "number": 1, "state": "CLOSED", "isDraft": false},
+            {"headRefName": "fix", "number": 2, "state": "CLOSED", "isDraft": false}
+        ]"#;
+        let prs = parse_pr_list(json);
+
+        // An open PR wins over merged and closed ones
+        let feature = prs["feature"];
+        assert_eq!(
+            (feature.number, feature.state, feature.is_draft),
+            (5, PrState::Open, true)
+        );
+        // Otherwise the newest
+        assert_eq!(prs["fix"].number, 2);
+        assert!(!prs.contains_key("main"));
+
+        assert!(parse_pr_list(b"not json").is_empty());
+    }
+
+    #[test]
+    fn parses_mr_list() {
+        let json = br#"[
+            {"source_branch": "feature", "iid": 8, "state": "merged", "draft": false},
+            {"source_branch": "feature", "iid": 9, "state": "opened", "draft": true},
+            {"source_branch": "fix", "iid": 2, "state": "locked"}
+        ]"#;
+        let mrs = parse_mr_list(json);
+
+        let feature = mrs["feature"];
+        assert_eq!(
+            (
+                feature.provider,
+                feature.number,
+                feature.state,
+                feature.is_draft
+            ),
+            (PrProvider::GitLab, 9, PrState::Open, true)
+        );
+        assert_eq!(mrs["fix"].state, PrState::Closed);
+        assert_eq!(PrProvider::GitLab.sigil(), '!');
+    }
 }
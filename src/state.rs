@@ -0,0 +1,110 @@
+//! Small per-repository state that survives between runs.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Returns `$XDG_STATE_HOME/git-checkoutui`, or `~/.local/state/git-checkoutui`.
+fn state_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("git-checkoutui"))
+}
+
+/// Returns `$XDG_CACHE_HOME/git-checkoutui`, or `~/.cache/git-checkoutui`.
+fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("git-checkoutui"))
+}
+
+/// A file name for the repository at `repo_path`, a hash of the path so
+/// different repositories don't clobber each other.
+fn repo_key(repo_path: &Path) -> String {
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust releases
+    let hash = repo_path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The state file for the repository at `repo_path`.
+fn state_file(repo_path: &Path) -> Option<PathBuf> {
+    Some(state_dir()?.join(repo_key(repo_path)))
+}
+
+fn pr_cache_file(repo_path: &Path) -> Option<PathBuf> {
+    Some(cache_dir()?.join(format!("{}.prs.json", repo_key(repo_path))))
+}
+
+/// The branch that was highlighted when the tool last exited in this repository.
+pub fn load_last_selected(repo_path: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(state_file(repo_path)?).ok()?;
+    let name = text.trim_end_matches('\n');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+pub fn save_last_selected(repo_path: &Path, branch: &str) -> io::Result<()> {
+    let Some(path) = state_file(repo_path) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, format!("{}\n", branch))
+}
+
+/// Branches pinned with `f` in this repository, in the order they were pinned.
+pub fn load_pinned(repo_path: &Path) -> Vec<String> {
+    let Some(path) = state_file(repo_path) else {
+        return Vec::new();
+    };
+    std::fs::read_to_string(path.with_extension("pinned"))
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+pub fn save_pinned(repo_path: &Path, branches: &[String]) -> io::Result<()> {
+    let Some(path) = state_file(repo_path) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text: String = branches.iter().map(|name| format!("{}\n", name)).collect();
+    std::fs::write(path.with_extension("pinned"), text)
+}
+
+/// The `gh pr list` output saved by `save_pr_cache`, if it's younger than
+/// `max_age`.
+pub fn load_pr_cache(repo_path: &Path, max_age: Duration) -> Option<Vec<u8>> {
+    let path = pr_cache_file(repo_path)?;
+    let age = std::fs::metadata(&path)
+        .ok()?
+        .modified()
+        .ok()?
+        .elapsed()
+        .ok()?;
+    if age > max_age {
+        return None;
+    }
+    std::fs::read(path).ok()
+}
+
+pub fn save_pr_cache(repo_path: &Path, json: &[u8]) -> io::Result<()> {
+    let Some(path) = pr_cache_file(repo_path) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, json)
+}